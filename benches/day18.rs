@@ -0,0 +1,26 @@
+//! Times day18 on a generated 100 line input, `benches/day18_input.txt`.
+//!
+//! Before snailfish numbers were flat lists of values and depths, they were boxed trees of
+//! pairs. Measured with this bench on the same input:
+//!
+//! | version    | part_1  | part_2  |
+//! |------------|---------|---------|
+//! | boxed tree | ~2.0 ms | ~24 ms  |
+//! | flat lists | ~330 µs | ~4.8 ms |
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+#[allow(dead_code)]
+#[path = "../src/day18.rs"]
+mod day18;
+
+fn bench(c: &mut Criterion) {
+    let snails = day18::generator(include_str!("day18_input.txt"));
+    let mut group = c.benchmark_group("day18");
+    group.bench_function("part_1", |b| b.iter(|| day18::part_1(&snails)));
+    group.bench_function("part_2", |b| b.iter(|| day18::part_2(&snails)));
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
@@ -1,4 +1,12 @@
+use std::{
+    fmt::Write,
+    hash::Hash,
+    io::{Error, ErrorKind},
+    str::FromStr,
+};
+
 use fnv::{FnvHashMap, FnvHashSet};
+#[cfg(test)]
 use itertools::Itertools;
 use parse_display::{Display, FromStr};
 
@@ -9,160 +17,575 @@ pub struct Edge {
     pub vert_b: String,
 }
 
-pub fn generator(
-    input: &str,
-) -> (
-    FnvHashMap<u32, Vec<u32>>,
-    FnvHashMap<u32, (bool, String)>,
-    u32,
-    u32,
-) {
-    let edges: Vec<Edge> = input.lines().map(|l| l.parse::<Edge>().unwrap()).collect();
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CaveSize {
+    /// named in upper case, may be visited any number of times
+    Big,
+    /// named in lower case
+    Small,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cave {
+    pub name: String,
+    pub size: CaveSize,
+}
+
+/// The caves with the passages between them, each cave identified by its index.
+#[derive(Debug, Clone)]
+pub struct CaveGraph {
+    caves: Vec<Cave>,
+    ids: FnvHashMap<String, u32>,
+    neighbours: Vec<Vec<u32>>,
+    start: u32,
+    end: u32,
+}
+
+impl CaveGraph {
+    pub fn caves(&self) -> &[Cave] {
+        &self.caves
+    }
+
+    pub fn cave(&self, id: u32) -> &Cave {
+        &self.caves[id as usize]
+    }
+
+    pub fn id(&self, name: &str) -> Option<u32> {
+        self.ids.get(name).copied()
+    }
+
+    /// Caves connected to `id`, in the order of the passages in the input.
+    pub fn neighbours(&self, id: u32) -> &[u32] {
+        &self.neighbours[id as usize]
+    }
+
+    pub fn start(&self) -> u32 {
+        self.start
+    }
+
+    pub fn end(&self) -> u32 {
+        self.end
+    }
+
+    /// Looks up the cave called `name`, adding it if it is new.
+    fn add_cave(&mut self, name: &str) -> Result<u32, Error> {
+        if let Some(id) = self.id(name) {
+            return Ok(id);
+        }
+        let size = if name.to_ascii_lowercase() == name {
+            CaveSize::Small
+        } else if name.to_ascii_uppercase() == name {
+            CaveSize::Big
+        } else {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Cave '{name}' is neither big nor small"),
+            ));
+        };
+        let id = self.caves.len() as u32;
+        self.caves.push(Cave {
+            name: name.to_string(),
+            size,
+        });
+        self.ids.insert(name.to_string(), id);
+        self.neighbours.push(Vec::new());
+        Ok(id)
+    }
 
-    let nodes: FnvHashMap<u32, (bool, String)> = (0..)
-        .zip(
-            edges
+    /// Number of paths from start to end that `policy` allows. Paths never return to start
+    /// and finish at the first visit of end. Fails if there are infinitely many such paths or
+    /// more than fit into 128 bits.
+    pub fn count_paths<P: VisitPolicy>(&self, policy: &P) -> Result<u128, Error> {
+        let mut counter = PathCounter {
+            graph: self,
+            policy,
+            memo: FnvHashMap::default(),
+            on_path: FnvHashSet::default(),
+            cycles: Vec::new(),
+        };
+        let paths = counter.count((self.start, policy.start(self)?))?;
+
+        // a state that is reached again from itself repeats forever, which only matters if
+        // it leads to the end at all
+        match counter.cycles.iter().find(|state| counter.memo[state] > 0) {
+            Some((cave, _)) => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Infinitely many paths lead through cave {}",
+                    self.cave(*cave).name
+                ),
+            )),
+            None => Ok(paths),
+        }
+    }
+
+    /// The paths `count_paths` counts, as the names of their caves. With infinitely many paths
+    /// the walk may get lost in a loop of big caves, so check `count_paths` first.
+    pub fn paths<'a, P: VisitPolicy>(&'a self, policy: &'a P) -> Result<Paths<'a, P>, Error> {
+        Ok(Paths {
+            graph: self,
+            policy,
+            stack: vec![(self.start, 0, policy.start(self)?)],
+        })
+    }
+
+    /// Passages as pairs of caves, the smaller ID first, each only once.
+    pub fn passages(&self) -> Vec<(u32, u32)> {
+        let mut passages: Vec<(u32, u32)> = (0..self.caves.len() as u32)
+            .flat_map(|a| self.neighbours(a).iter().map(move |&b| (a, b)))
+            .filter(|(a, b)| a <= b)
+            .collect();
+        passages.sort_unstable();
+        passages.dedup();
+        passages
+    }
+
+    /// For every passage, the number of paths allowed by `policy` that go through it.
+    pub fn heat<P: VisitPolicy>(&self, policy: &P) -> Result<FnvHashMap<(u32, u32), u128>, Error> {
+        // walking through infinitely many paths would never finish
+        self.count_paths(policy)?;
+        let mut heat: FnvHashMap<(u32, u32), u128> = self
+            .passages()
+            .into_iter()
+            .map(|passage| (passage, 0))
+            .collect();
+        for path in self.paths(policy)? {
+            let passages: FnvHashSet<(u32, u32)> = path
+                .windows(2)
+                .map(|pair| {
+                    let (a, b) = (self.ids[pair[0]], self.ids[pair[1]]);
+                    (a.min(b), a.max(b))
+                })
+                .collect();
+            for passage in passages {
+                *heat.get_mut(&passage).unwrap() += 1;
+            }
+        }
+        Ok(heat)
+    }
+
+    /// The caves in Graphviz DOT, big caves as boxes and small ones as ellipses, with start
+    /// and end filled in.
+    pub fn to_dot(&self, overlay: &Overlay) -> Result<String, Error> {
+        let mut on_path = FnvHashSet::default();
+        let mut visited = FnvHashSet::default();
+        if let Overlay::Path(path) = overlay {
+            let ids = path
                 .iter()
-                .flat_map(|e| [e.vert_a.clone(), e.vert_b.clone()].into_iter())
-                .unique()
-                .map(|v| (v.to_ascii_lowercase().eq(&v), v)),
-        )
-        .collect();
+                .map(|&name| find_cave(self, name))
+                .collect::<Result<Vec<u32>, Error>>()?;
+            for pair in ids.windows(2) {
+                if !self.neighbours(pair[0]).contains(&pair[1]) {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "There is no passage from {} to {}",
+                            self.cave(pair[0]).name,
+                            self.cave(pair[1]).name
+                        ),
+                    ));
+                }
+                on_path.insert((pair[0].min(pair[1]), pair[0].max(pair[1])));
+            }
+            visited.extend(ids);
+        }
 
-    let mut map: FnvHashMap<u32, Vec<u32>> = FnvHashMap::default();
-    edges
-        .iter()
-        .map(|edge| {
-            (
-                get_reverse(&nodes, &edge.vert_a),
-                get_reverse(&nodes, &edge.vert_b),
+        let mut dot = "graph caves {\n".to_string();
+        for (id, cave) in self.caves.iter().enumerate() {
+            let id = id as u32;
+            let mut attributes = vec![match cave.size {
+                CaveSize::Big => "shape=box",
+                CaveSize::Small => "shape=ellipse",
+            }];
+            if id == self.start {
+                attributes.push("style=filled, fillcolor=palegreen");
+            } else if id == self.end {
+                attributes.push("style=filled, fillcolor=lightcoral");
+            }
+            if visited.contains(&id) {
+                attributes.push("color=red, penwidth=3");
+            }
+            writeln!(dot, "    \"{}\" [{}];", cave.name, attributes.join(", ")).unwrap();
+        }
+        let hottest = match overlay {
+            Overlay::Heat(heat) => heat.values().copied().max().unwrap_or(0).max(1),
+            _ => 1,
+        };
+        for passage in self.passages() {
+            let (a, b) = passage;
+            write!(
+                dot,
+                "    \"{}\" -- \"{}\"",
+                self.cave(a).name,
+                self.cave(b).name
             )
+            .unwrap();
+            match overlay {
+                Overlay::Path(_) if on_path.contains(&passage) => {
+                    dot.push_str(" [color=red, penwidth=3]")
+                }
+                Overlay::Heat(heat) => {
+                    let paths = heat.get(&passage).copied().unwrap_or(0);
+                    let width = 1.0 + 4.0 * paths as f64 / hottest as f64;
+                    write!(dot, " [label=\"{paths}\", penwidth={width:.1}").unwrap();
+                    if paths == 0 {
+                        dot.push_str(", style=dashed");
+                    }
+                    dot.push(']');
+                }
+                _ => {}
+            }
+            dot.push_str(";\n");
+        }
+        dot.push_str("}\n");
+        Ok(dot)
+    }
+}
+
+/// What `CaveGraph::to_dot` draws on top of the caves.
+pub enum Overlay<'a> {
+    Plain,
+    /// one path, as the names of its caves
+    Path(&'a [&'a str]),
+    /// the number of paths through each passage, see `CaveGraph::heat`
+    Heat(&'a FnvHashMap<(u32, u32), u128>),
+}
+
+impl FromStr for CaveGraph {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut graph = CaveGraph {
+            caves: Vec::new(),
+            ids: FnvHashMap::default(),
+            neighbours: Vec::new(),
+            start: 0,
+            end: 0,
+        };
+        for line in s.lines() {
+            let edge: Edge = line.parse().map_err(|_| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Expected a passage like a-b, found '{line}'"),
+                )
+            })?;
+            let a = graph.add_cave(&edge.vert_a)?;
+            let b = graph.add_cave(&edge.vert_b)?;
+            graph.neighbours[a as usize].push(b);
+            if a != b {
+                graph.neighbours[b as usize].push(a);
+            }
+        }
+        let find = |name| {
+            graph.id(name).ok_or_else(|| {
+                Error::new(ErrorKind::InvalidData, format!("There is no {name} cave"))
+            })
+        };
+        let (start, end) = (find("start")?, find("end")?);
+        graph.start = start;
+        graph.end = end;
+        Ok(graph)
+    }
+}
+
+pub fn generator(input: &str) -> CaveGraph {
+    input.parse().unwrap()
+}
+
+pub fn part_1(graph: &CaveGraph) -> u64 {
+    graph.count_paths(&Revisits(0)).unwrap().try_into().unwrap()
+}
+
+pub fn part_2(graph: &CaveGraph) -> u64 {
+    graph.count_paths(&Revisits(1)).unwrap().try_into().unwrap()
+}
+
+/// Decides where a path may go next. All a policy knows about a path is its `State`, so paths
+/// that reach the same cave in the same state carry on alike.
+pub trait VisitPolicy {
+    type State: Clone + Eq + Hash;
+
+    /// The state of a path that has just left start.
+    fn start(&self, graph: &CaveGraph) -> Result<Self::State, Error>;
+
+    /// The state after going on to `cave`, `None` if the path may not go there.
+    fn enter(&self, graph: &CaveGraph, state: &Self::State, cave: u32) -> Option<Self::State>;
+
+    /// Whether a path in `state` counts once it reaches end.
+    fn finish(&self, _state: &Self::State) -> bool {
+        true
+    }
+}
+
+/// The bit of `cave` in a set of caves, which only has room for 128 of them.
+fn cave_bit(graph: &CaveGraph, cave: u32) -> Result<u128, Error> {
+    if graph.caves.len() > 128 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Cannot track visits to {} caves", graph.caves.len()),
+        ));
+    }
+    Ok(1 << cave)
+}
+
+/// Every small cave at most this many times.
+#[derive(Debug, Clone, Copy)]
+pub struct SmallCaveLimit(pub u32);
+
+impl VisitPolicy for SmallCaveLimit {
+    /// visits to every cave
+    type State = Vec<u32>;
+
+    fn start(&self, graph: &CaveGraph) -> Result<Self::State, Error> {
+        Ok(vec![0; graph.caves.len()])
+    }
+
+    fn enter(&self, graph: &CaveGraph, state: &Self::State, cave: u32) -> Option<Self::State> {
+        if graph.cave(cave).size == CaveSize::Big {
+            return Some(state.clone());
+        }
+        if state[cave as usize] >= self.0 {
+            return None;
+        }
+        let mut state = state.clone();
+        state[cave as usize] += 1;
+        Some(state)
+    }
+}
+
+/// Small caves once, except for this many of them that may be visited twice.
+#[derive(Debug, Clone, Copy)]
+pub struct Revisits(pub u32);
+
+impl VisitPolicy for Revisits {
+    /// small caves visited once and those visited twice
+    type State = (u128, u128);
+
+    fn start(&self, graph: &CaveGraph) -> Result<Self::State, Error> {
+        // start is never entered again, so it needs no bit of its own
+        cave_bit(graph, graph.start)?;
+        Ok((0, 0))
+    }
+
+    fn enter(&self, graph: &CaveGraph, state: &Self::State, cave: u32) -> Option<Self::State> {
+        let (once, twice) = *state;
+        let bit = 1 << cave;
+        match graph.cave(cave).size {
+            CaveSize::Big => Some(*state),
+            CaveSize::Small if once & bit == 0 => Some((once | bit, twice)),
+            CaveSize::Small if twice & bit == 0 && twice.count_ones() < self.0 => {
+                Some((once, twice | bit))
+            }
+            CaveSize::Small => None,
+        }
+    }
+}
+
+/// A limit on the visits to every cave, `None` for no limit.
+#[derive(Debug, Clone)]
+pub struct CaveLimits {
+    limits: Vec<Option<u32>>,
+}
+
+impl CaveLimits {
+    /// Big caves without limit and small ones once, except for the caves named in `limits`.
+    pub fn new(graph: &CaveGraph, limits: &[(&str, Option<u32>)]) -> Result<CaveLimits, Error> {
+        let mut cave_limits: Vec<Option<u32>> = graph
+            .caves
+            .iter()
+            .map(|cave| match cave.size {
+                CaveSize::Big => None,
+                CaveSize::Small => Some(1),
+            })
+            .collect();
+        for &(name, limit) in limits {
+            cave_limits[find_cave(graph, name)? as usize] = limit;
+        }
+        Ok(CaveLimits {
+            limits: cave_limits,
         })
-        .flat_map(|tuple| [(tuple.0, tuple.1), (tuple.1, tuple.0)].into_iter())
-        .for_each(|entry| {
-            map.entry(entry.0)
-                .or_insert_with(|| Vec::new())
-                .push(entry.1);
-        });
-    let start = get_reverse(&nodes, "start");
-    let end = get_reverse(&nodes, "end");
-    (map, nodes, start, end)
-}
-
-fn get_reverse(node_map: &FnvHashMap<u32, (bool, String)>, val: &str) -> u32 {
-    *node_map.iter().find(|entry| entry.1 .1.eq(&val)).unwrap().0
-}
-
-pub fn part_1(
-    (map, nodes, start, end): &(
-        FnvHashMap<u32, Vec<u32>>,
-        FnvHashMap<u32, (bool, String)>,
-        u32,
-        u32,
-    ),
-) -> u32 {
-    let mut visited = FnvHashSet::default();
-    // println!("map: {:?}", map);
-    // println!("nodes: {:?}", nodes);
-    visit_rec(*start, &map, &mut visited, &nodes, *end).unwrap()
-}
-
-pub fn part_2(
-    (map, nodes, start, end): &(
-        FnvHashMap<u32, Vec<u32>>,
-        FnvHashMap<u32, (bool, String)>,
-        u32,
-        u32,
-    ),
-) -> u32 {
-    let mut visited = FnvHashMap::default();
-    visit_rec_2(
-        *start,
-        &map,
-        &mut visited,
-        &nodes,
-        *start,
-        *end,
-        true,
-    )
-    .unwrap()
-}
-
-fn visit_rec(
-    curr_node: u32,
-    map: &FnvHashMap<u32, Vec<u32>>,
-    visited: &mut FnvHashSet<u32>,
-    lookup: &FnvHashMap<u32, (bool, String)>,
-    end: u32,
-) -> Option<u32> {
-    if curr_node == end {
-        return Some(1);
     }
-    let add = |a, b| a + b;
+}
 
-    visited.insert(curr_node);
+impl VisitPolicy for CaveLimits {
+    /// visits to every cave with a limit
+    type State = Vec<u32>;
 
-    let mut sub_paths = None;
+    fn start(&self, graph: &CaveGraph) -> Result<Self::State, Error> {
+        Ok(vec![0; graph.caves.len()])
+    }
 
-    for node in map.get(&curr_node).unwrap() {
-        if !lookup.get(node).unwrap().0 || !visited.contains(node) {
-            let res = visit_rec(*node, map, visited, lookup, end);
-            sub_paths = match (sub_paths, res) {
-                (Some(a), Some(b)) => Some(add(a, b)),
-                (sub_paths, res) => sub_paths.or(res),
-            };
+    fn enter(&self, _graph: &CaveGraph, state: &Self::State, cave: u32) -> Option<Self::State> {
+        let limit = match self.limits[cave as usize] {
+            Some(limit) => limit,
+            None => return Some(state.clone()),
+        };
+        if state[cave as usize] >= limit {
+            return None;
         }
+        let mut state = state.clone();
+        state[cave as usize] += 1;
+        Some(state)
     }
+}
 
-    visited.remove(&curr_node);
+/// Another policy, restricted to paths that avoid the `forbidden` caves and pass through all
+/// of the `mandatory` ones.
+#[derive(Debug, Clone)]
+pub struct Waypoints<P> {
+    policy: P,
+    forbidden: u128,
+    mandatory: u128,
+}
 
-    sub_paths
+impl<P: VisitPolicy> Waypoints<P> {
+    pub fn new(
+        graph: &CaveGraph,
+        policy: P,
+        forbidden: &[&str],
+        mandatory: &[&str],
+    ) -> Result<Waypoints<P>, Error> {
+        let caves = |names: &[&str]| {
+            names.iter().try_fold(0, |caves, name| {
+                Ok::<_, Error>(caves | cave_bit(graph, find_cave(graph, name)?)?)
+            })
+        };
+        Ok(Waypoints {
+            policy,
+            forbidden: caves(forbidden)?,
+            mandatory: caves(mandatory)?,
+        })
+    }
 }
 
-fn visit_rec_2(
-    curr_node: u32,
-    map: &FnvHashMap<u32, Vec<u32>>,
-    visited: &mut FnvHashMap<u32, u32>,
-    lookup: &FnvHashMap<u32, (bool, String)>,
-    start: u32,
-    end: u32,
-    allow_twice: bool,
-) -> Option<u32> {
-    if curr_node == end {
-        return Some(1);
+impl<P: VisitPolicy> VisitPolicy for Waypoints<P> {
+    /// the state of the other policy and the mandatory caves still to visit
+    type State = (P::State, u128);
+
+    fn start(&self, graph: &CaveGraph) -> Result<Self::State, Error> {
+        let bit = cave_bit(graph, graph.start)?;
+        Ok((self.policy.start(graph)?, self.mandatory & !bit))
     }
-    let add = |a, b| a + b;
 
-    *visited.entry(curr_node).or_insert(0) += 1;
+    fn enter(&self, graph: &CaveGraph, state: &Self::State, cave: u32) -> Option<Self::State> {
+        let bit = 1 << cave;
+        if self.forbidden & bit != 0 {
+            return None;
+        }
+        let (inner, missing) = state;
+        Some((self.policy.enter(graph, inner, cave)?, missing & !bit))
+    }
 
-    let mut sub_paths = None;
+    fn finish(&self, (inner, missing): &Self::State) -> bool {
+        *missing == 0 && self.policy.finish(inner)
+    }
+}
 
-    for node in map.get(&curr_node).unwrap() {
-        let is_start = *node == start; // may never revisit
+fn find_cave(graph: &CaveGraph, name: &str) -> Result<u32, Error> {
+    graph.id(name).ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("There is no cave called {name}"),
+        )
+    })
+}
 
-        let big_cave = !lookup.get(node).unwrap().0;
-        let has_visited = visited.get(node).unwrap_or(&0) > &0;
-        let is_end = *node == end; // may only revisit once
+/// A cave and the state of the visit policy in it.
+type State<S> = (u32, S);
 
-        if is_start {
-            continue;
-        } else if big_cave || is_end || !has_visited {
-            let res = visit_rec_2(*node, map, visited, lookup, start, end, allow_twice);
-            sub_paths = match (sub_paths, res) {
-                (Some(a), Some(b)) => Some(add(a, b)),
-                (sub_paths, res) => sub_paths.or(res),
+struct PathCounter<'a, P: VisitPolicy> {
+    graph: &'a CaveGraph,
+    policy: &'a P,
+    memo: FnvHashMap<State<P::State>, u128>,
+    on_path: FnvHashSet<State<P::State>>,
+    cycles: Vec<State<P::State>>,
+}
+
+impl<P: VisitPolicy> PathCounter<'_, P> {
+    /// Paths to the end from `state`. States already on the current path count 0, they are
+    /// remembered in `cycles` instead.
+    fn count(&mut self, state: State<P::State>) -> Result<u128, Error> {
+        if let Some(&paths) = self.memo.get(&state) {
+            return Ok(paths);
+        }
+        if !self.on_path.insert(state.clone()) {
+            self.cycles.push(state);
+            return Ok(0);
+        }
+        let (curr_cave, policy_state) = &state;
+        let mut paths = 0u128;
+
+        for &cave in self.graph.neighbours(*curr_cave) {
+            if cave == self.graph.start {
+                continue;
+            }
+            let next = match self.policy.enter(self.graph, policy_state, cave) {
+                Some(next) => next,
+                None => continue,
             };
-        } else if allow_twice {
-            let res = visit_rec_2(*node, map, visited, lookup, start, end, false);
-            sub_paths = match (sub_paths, res) {
-                (Some(a), Some(b)) => Some(add(a, b)),
-                (sub_paths, res) => sub_paths.or(res),
+            let sub_paths = if cave == self.graph.end {
+                self.policy.finish(&next) as u128
+            } else {
+                self.count((cave, next))?
             };
+            paths = paths.checked_add(sub_paths).ok_or_else(|| {
+                Error::new(ErrorKind::InvalidData, "More paths than fit into 128 bits")
+            })?;
         }
+
+        self.on_path.remove(&state);
+        self.memo.insert(state, paths);
+        Ok(paths)
     }
+}
 
-    *visited.entry(curr_node).or_insert(1) -= 1;
-    sub_paths
+/// Depth first walk through the caves, see `CaveGraph::paths`.
+pub struct Paths<'a, P: VisitPolicy> {
+    graph: &'a CaveGraph,
+    policy: &'a P,
+    /// caves of the current path, each with the index of its next neighbour to try and the
+    /// state of the policy in it
+    stack: Vec<(u32, usize, P::State)>,
+}
+
+impl<'a, P: VisitPolicy> Iterator for Paths<'a, P> {
+    type Item = Vec<&'a str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let graph = self.graph;
+        loop {
+            let (curr_cave, idx, state) = self.stack.last_mut()?;
+            let cave = match graph.neighbours(*curr_cave).get(*idx) {
+                Some(&cave) => cave,
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            *idx += 1;
+            if cave == graph.start {
+                continue;
+            }
+            let next = match self.policy.enter(graph, state, cave) {
+                Some(next) => next,
+                None => continue,
+            };
+
+            if cave == graph.end {
+                if !self.policy.finish(&next) {
+                    continue;
+                }
+                let mut path: Vec<&str> = self
+                    .stack
+                    .iter()
+                    .map(|(cave, _, _)| graph.cave(*cave).name.as_str())
+                    .collect();
+                path.push(&graph.cave(cave).name);
+                return Some(path);
+            }
+            self.stack.push((cave, 0, next));
+        }
+    }
 }
 
 #[test]
@@ -178,3 +601,250 @@ pub fn test() {
     assert_eq!(10, part_1(&generator(&input)));
     assert_eq!(36, part_2(&generator(&input)));
 }
+
+#[test]
+fn test_count_paths() {
+    let input = "dc-end\n\
+    HN-start\n\
+    start-kj\n\
+    dc-start\n\
+    dc-HN\n\
+    LN-dc\n\
+    HN-end\n\
+    kj-sa\n\
+    kj-HN\n\
+    kj-dc";
+    assert_eq!(19, part_1(&generator(input)));
+    assert_eq!(103, part_2(&generator(input)));
+
+    let input = "fs-end\n\
+    he-DX\n\
+    fs-he\n\
+    start-DX\n\
+    pj-DX\n\
+    end-zg\n\
+    zg-sl\n\
+    zg-pj\n\
+    pj-he\n\
+    RW-he\n\
+    fs-DX\n\
+    pj-RW\n\
+    zg-RW\n\
+    start-pj\n\
+    he-WI\n\
+    zg-he\n\
+    pj-fs\n\
+    start-RW";
+    assert_eq!(226, part_1(&generator(input)));
+    assert_eq!(3509, part_2(&generator(input)));
+
+    // every small cave linked to every other one
+    let caves: Vec<String> = (0..12).map(|cave| format!("c{cave}")).collect();
+    let mut edges: Vec<String> = caves
+        .iter()
+        .tuple_combinations()
+        .map(|(a, b)| format!("{a}-{b}"))
+        .collect();
+    edges.extend(caves.iter().map(|cave| format!("start-{cave}\n{cave}-end")));
+    let graph = generator(&edges.join("\n"));
+    // a path is an ordered choice of distinct small caves
+    let arrangements: u128 = (1..=12)
+        .map(|len| (12 - len + 1..=12).product::<u128>())
+        .sum();
+    assert_eq!(arrangements, graph.count_paths(&Revisits(0)).unwrap());
+    assert!(graph.count_paths(&Revisits(1)).unwrap() > arrangements);
+
+    let graph = generator("start-A\nA-B\nB-end");
+    let error = graph.count_paths(&Revisits(0)).unwrap_err();
+    assert!(
+        ["A", "B"]
+            .iter()
+            .any(|cave| error.to_string()
+                == format!("Infinitely many paths lead through cave {cave}"))
+    );
+    // big caves next to each other are fine if they don't lead to the end
+    let graph = generator("start-A\nA-B\nstart-end");
+    assert_eq!(1, graph.count_paths(&Revisits(1)).unwrap());
+}
+
+#[test]
+fn test_cave_graph() {
+    let graph = generator("start-A\nstart-b\nA-c\nA-b\nb-d\nA-end\nb-end");
+    let a = graph.id("A").unwrap();
+    assert_eq!(CaveSize::Big, graph.cave(a).size);
+    assert_eq!("b", graph.cave(graph.neighbours(a)[2]).name);
+    assert_eq!(Some(graph.start()), graph.id("start"));
+    assert_eq!(None, graph.id("e"));
+    assert_eq!(6, graph.caves().len());
+
+    let paths: Vec<String> = graph
+        .paths(&Revisits(0))
+        .unwrap()
+        .map(|path| path.join(","))
+        .sorted()
+        .collect();
+    assert_eq!(
+        vec![
+            "start,A,b,A,c,A,end",
+            "start,A,b,A,end",
+            "start,A,b,end",
+            "start,A,c,A,b,A,end",
+            "start,A,c,A,b,end",
+            "start,A,c,A,end",
+            "start,A,end",
+            "start,b,A,c,A,end",
+            "start,b,A,end",
+            "start,b,end",
+        ],
+        paths
+    );
+    let paths: Vec<Vec<&str>> = graph.paths(&Revisits(1)).unwrap().collect();
+    assert_eq!(36, paths.len());
+    assert_eq!(36, paths.iter().unique().count());
+    assert!(paths.contains(&vec!["start", "A", "b", "d", "b", "A", "c", "A", "end"]));
+    assert!(paths
+        .iter()
+        .all(|path| path.iter().filter(|&&cave| cave == "start").count() == 1));
+
+    let error = |s: &str| s.parse::<CaveGraph>().unwrap_err().to_string();
+    assert_eq!(
+        "Cave 'Ab' is neither big nor small",
+        error("start-Ab\nAb-end")
+    );
+    assert_eq!("There is no end cave", error("start-A"));
+    assert_eq!("Expected a passage like a-b, found 'start'", error("start"));
+}
+
+#[test]
+fn test_visit_policies() {
+    let graph = generator("start-A\nstart-b\nA-c\nA-b\nb-d\nA-end\nb-end");
+    assert_eq!(10, graph.count_paths(&SmallCaveLimit(1)).unwrap());
+    let default_limits = CaveLimits::new(&graph, &[]).unwrap();
+    assert_eq!(10, graph.count_paths(&default_limits).unwrap());
+    // any number of small caves twice is more than just one of them
+    let twice = graph.count_paths(&SmallCaveLimit(2)).unwrap();
+    assert!(twice > 36);
+    assert_eq!(twice, graph.count_paths(&Revisits(3)).unwrap());
+    assert_eq!(
+        twice,
+        graph.paths(&SmallCaveLimit(2)).unwrap().count() as u128
+    );
+
+    // only b twice is part 2 without the paths that visit c or d twice
+    let b_twice = CaveLimits::new(&graph, &[("b", Some(2))]).unwrap();
+    let paths: Vec<Vec<&str>> = graph.paths(&b_twice).unwrap().collect();
+    assert_eq!(graph.count_paths(&b_twice).unwrap(), paths.len() as u128);
+    assert!(paths.contains(&vec!["start", "A", "b", "A", "b", "end"]));
+    assert!(!paths
+        .iter()
+        .any(|path| path.iter().filter(|&&cave| cave == "c").count() > 1));
+
+    fn joined<'a>(paths: impl Iterator<Item = Vec<&'a str>>) -> Vec<String> {
+        paths.map(|path| path.join(",")).sorted().collect()
+    }
+    // limiting A to two visits leaves only the paths through it at most twice
+    let a_twice = CaveLimits::new(&graph, &[("A", Some(2))]).unwrap();
+    assert_eq!(
+        vec![
+            "start,A,b,A,end",
+            "start,A,b,end",
+            "start,A,c,A,b,end",
+            "start,A,c,A,end",
+            "start,A,end",
+            "start,b,A,c,A,end",
+            "start,b,A,end",
+            "start,b,end"
+        ],
+        joined(graph.paths(&a_twice).unwrap())
+    );
+
+    let via_c = Waypoints::new(&graph, Revisits(0), &[], &["c"]).unwrap();
+    assert_eq!(5, graph.count_paths(&via_c).unwrap());
+    let without_b = Waypoints::new(&graph, Revisits(1), &["b"], &[]).unwrap();
+    assert_eq!(
+        vec!["start,A,c,A,c,A,end", "start,A,c,A,end", "start,A,end"],
+        joined(graph.paths(&without_b).unwrap())
+    );
+    assert_eq!(
+        "There is no cave called x",
+        Waypoints::new(&graph, Revisits(0), &["x"], &[])
+            .err()
+            .unwrap()
+            .to_string()
+    );
+
+    let graph = generator("start-A\nA-B\nB-end");
+    assert!(graph.count_paths(&Revisits(0)).is_err());
+    let limited = CaveLimits::new(&graph, &[("A", Some(2)), ("B", Some(2))]).unwrap();
+    assert_eq!(2, graph.count_paths(&limited).unwrap());
+}
+
+#[test]
+fn test_dot() {
+    let graph = generator("start-A\nstart-b\nA-b\nA-end\nb-end");
+    assert_eq!(
+        "graph caves {\n\
+        \x20   \"start\" [shape=ellipse, style=filled, fillcolor=palegreen];\n\
+        \x20   \"A\" [shape=box];\n\
+        \x20   \"b\" [shape=ellipse];\n\
+        \x20   \"end\" [shape=ellipse, style=filled, fillcolor=lightcoral];\n\
+        \x20   \"start\" -- \"A\";\n\
+        \x20   \"start\" -- \"b\";\n\
+        \x20   \"A\" -- \"b\";\n\
+        \x20   \"A\" -- \"end\";\n\
+        \x20   \"b\" -- \"end\";\n\
+        }\n",
+        graph.to_dot(&Overlay::Plain).unwrap()
+    );
+
+    let path = ["start", "b", "A", "end"];
+    let dot = graph.to_dot(&Overlay::Path(&path)).unwrap();
+    assert!(dot.contains("\"b\" [shape=ellipse, color=red, penwidth=3];"));
+    assert!(dot.contains("\"A\" -- \"b\" [color=red, penwidth=3];"));
+    assert!(dot.contains("\"start\" -- \"A\";"));
+    assert_eq!(
+        "There is no passage from start to end",
+        graph
+            .to_dot(&Overlay::Path(&["start", "end"]))
+            .unwrap_err()
+            .to_string()
+    );
+
+    // start,A,end start,A,b,end start,A,b,A,end start,b,end start,b,A,end
+    let heat = graph.heat(&Revisits(0)).unwrap();
+    let (start, a, b, end) = (0, 1, 2, 3);
+    assert_eq!(3, heat[&(start, a)]);
+    assert_eq!(2, heat[&(start, b)]);
+    assert_eq!(3, heat[&(a, b)]);
+    assert_eq!(3, heat[&(a, end)]);
+    assert_eq!(
+        graph.count_paths(&Revisits(0)).unwrap() * 2,
+        heat[&(a, end)] + heat[&(b, end)] + heat[&(start, a)] + heat[&(start, b)]
+    );
+    let dot = graph.to_dot(&Overlay::Heat(&heat)).unwrap();
+    assert!(dot.contains("\"start\" -- \"A\" [label=\"3\", penwidth=5.0];"));
+    assert!(dot.contains("\"start\" -- \"b\" [label=\"2\", penwidth=3.7];"));
+
+    let avoid_a = Waypoints::new(&graph, Revisits(0), &["A"], &[]).unwrap();
+    let dot = graph
+        .to_dot(&Overlay::Heat(&graph.heat(&avoid_a).unwrap()))
+        .unwrap();
+    assert!(dot.contains("\"A\" -- \"end\" [label=\"0\", penwidth=1.0, style=dashed];"));
+
+    // a passage from a cave to itself is a loop, walked in a single step
+    let graph = generator("start-b\nb-b\nb-end");
+    let (start, b, end) = (0, 1, 2);
+    assert_eq!(vec![(start, b), (b, b), (b, end)], graph.passages());
+    assert_eq!(2, graph.count_paths(&Revisits(1)).unwrap());
+    let heat = graph.heat(&Revisits(1)).unwrap();
+    assert_eq!(
+        (2, 1, 2),
+        (heat[&(start, b)], heat[&(b, b)], heat[&(b, end)])
+    );
+    let dot = graph
+        .to_dot(&Overlay::Path(&["start", "b", "end"]))
+        .unwrap();
+    assert!(dot.contains("\"b\" [shape=ellipse, color=red, penwidth=3];"));
+    assert!(dot.contains("\"b\" -- \"b\";"));
+    assert!(dot.contains("\"b\" -- \"end\" [color=red, penwidth=3];"));
+}
@@ -1,4 +1,7 @@
-use std::collections::HashSet;
+use std::{
+    collections::HashSet,
+    io::{Error, ErrorKind},
+};
 
 use parse_display::{Display, FromStr};
 
@@ -29,28 +32,83 @@ pub struct Manual {
     instructions: Vec<Instruction>,
 }
 
-impl Variant {
-    pub fn fold_dot(&self, dot: (u32, u32), value: u32) -> (u32, u32) {
-        match self {
-            &Self::VerticalFold => Variant::fold_vertical(dot, value),
-            &Self::HorizontalFold => Variant::fold_horizontal(dot, value),
+/// The transparent paper with its dots, `width` by `height` dots in size.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Paper {
+    dots: HashSet<(u32, u32)>,
+    width: u32,
+    height: u32,
+}
+
+impl Paper {
+    /// A paper just large enough for all of the dots.
+    pub fn new(dots: &[Dot]) -> Paper {
+        Paper {
+            dots: dots.iter().map(|dot| (dot.x, dot.y)).collect(),
+            width: dots.iter().map(|dot| dot.x + 1).max().unwrap_or(0),
+            height: dots.iter().map(|dot| dot.y + 1).max().unwrap_or(0),
         }
     }
 
-    pub fn fold_vertical((a, b): (u32, u32), value: u32) -> (u32, u32) {
-        if a > value {
-            (value - (a - value), b)
-        } else {
-            (a, b)
-        }
+    pub fn dots(&self) -> &HashSet<(u32, u32)> {
+        &self.dots
     }
 
-    pub fn fold_horizontal((a, b): (u32, u32), value: u32) -> (u32, u32) {
-        if b > value {
-            (a, value - (b - value))
-        } else {
-            (a, b)
+    /// Width and height of the paper.
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Folds the part beyond the fold line onto the other one. If it is the larger part, it
+    /// sticks out past the origin, so the coordinates shift to start at 0 again. The paper is
+    /// only as large as its dots, so a fold line off it folds nothing over and extends the
+    /// paper up to the line.
+    pub fn fold(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        let val = instruction.val;
+        if let Some((x, y)) = self.dots.iter().find(|&&(x, y)| match instruction.variant {
+            Variant::VerticalFold => x == val,
+            Variant::HorizontalFold => y == val,
+        }) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Dot {x},{y} lies on the line of {instruction}"),
+            ));
         }
+
+        let size = match instruction.variant {
+            Variant::VerticalFold => &mut self.width,
+            Variant::HorizontalFold => &mut self.height,
+        };
+        let folded_size = val.max(size.saturating_sub(val + 1));
+        *size = folded_size;
+        // the fold line lies right after the new last row or column
+        let fold = |c: u32| {
+            if c < val {
+                c + folded_size - val
+            } else {
+                folded_size + val - c
+            }
+        };
+        self.dots = self
+            .dots
+            .iter()
+            .map(|&(x, y)| match instruction.variant {
+                Variant::VerticalFold => (fold(x), y),
+                Variant::HorizontalFold => (x, fold(y)),
+            })
+            .collect();
+        Ok(())
+    }
+
+    /// Applies all `instructions`, returning the dimensions after each of them.
+    pub fn fold_all(&mut self, instructions: &[Instruction]) -> Result<Vec<(u32, u32)>, Error> {
+        instructions
+            .iter()
+            .map(|instruction| {
+                self.fold(instruction)?;
+                Ok(self.dimensions())
+            })
+            .collect()
     }
 }
 
@@ -73,48 +131,184 @@ pub fn generator(input: &str) -> Manual {
 }
 
 pub fn part_1(manual: &Manual) -> usize {
-    let mut coordinates = HashSet::new();
+    let mut paper = Paper::new(&manual.dots);
+    paper.fold(manual.instructions.first().unwrap()).unwrap();
+    paper.dots().len()
+}
 
-    manual.dots.iter().for_each(|dot| {
-        coordinates.insert((dot.x, dot.y));
-    });
+pub fn part_2(manual: &Manual) -> String {
+    let mut paper = Paper::new(&manual.dots);
+    paper.fold_all(&manual.instructions).unwrap();
+    read_letters(paper.dots()).unwrap()
+}
 
-    let first = manual.instructions.first().unwrap();
+/// Letters of the font the code is written in, 4 dots wide and 6 high. The 5 dots wide `Y`
+/// does not fit the 5 column stride of `read_letters` and is left out.
+const LETTERS: [(char, [&str; 6]); 17] = [
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
 
-    let coordinates: HashSet<(u32, u32)> = coordinates
-        .iter()
-        .map(|c| first.variant.fold_dot(*c, first.val))
-        .collect();
+/// The dots as `#` on a background of `.`, starting at the origin.
+pub fn render(dots: &HashSet<(u32, u32)>) -> String {
+    let x_max = dots.iter().map(|(x, _y)| *x).max().unwrap_or(0);
+    let y_max = dots.iter().map(|(_x, y)| *y).max().unwrap_or(0);
+    let mut picture = String::new();
+    for y in 0..=y_max {
+        for x in 0..=x_max {
+            picture.push(if dots.contains(&(x, y)) { '#' } else { '.' });
+        }
+        picture.push('\n');
+    }
+    picture
+}
 
-    coordinates.len()
+/// Reads the letters the dots spell, each 4 dots wide with a column of space after it.
+pub fn read_letters(dots: &HashSet<(u32, u32)>) -> Result<String, Error> {
+    let y_max = dots.iter().map(|(_x, y)| *y).max().unwrap_or(0);
+    if y_max >= 6 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "Letters are 6 dots high, these are {}:\n{}",
+                y_max + 1,
+                render(dots)
+            ),
+        ));
+    }
+    let letters = dots.iter().map(|(x, _y)| x / 5 + 1).max().unwrap_or(0);
+    (0..letters)
+        .map(|letter| {
+            let glyph: Vec<String> = (0..6)
+                .map(|y| {
+                    (letter * 5..letter * 5 + 5)
+                        .map(|x| if dots.contains(&(x, y)) { '#' } else { '.' })
+                        .collect()
+                })
+                .collect();
+            LETTERS
+                .iter()
+                .find(|(_, rows)| {
+                    rows.iter()
+                        .zip(&glyph)
+                        .all(|(row, line)| line == &format!("{row}."))
+                })
+                .map(|(char, _)| *char)
+                .ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::InvalidData,
+                        format!("Unknown letter {}:\n{}\n", letter + 1, glyph.join("\n")),
+                    )
+                })
+        })
+        .collect()
 }
 
-pub fn part_2(manual: &Manual) -> usize {
-    let mut coordinates = HashSet::new();
+#[test]
+fn test() {
+    let input = "6,10\n0,14\n9,10\n0,3\n10,4\n4,11\n6,0\n6,12\n4,1\n0,13\n10,12\n3,4\n\
+    3,0\n8,4\n1,10\n2,14\n8,10\n9,0\n\n\
+    fold along y=7\n\
+    fold along x=5";
+    let manual = generator(input);
+    assert_eq!(17, part_1(&manual));
 
-    manual.dots.iter().for_each(|dot| {
-        coordinates.insert((dot.x, dot.y));
-    });
+    // the example folds into a square, which is no letter
+    let mut paper = Paper::new(&manual.dots);
+    assert_eq!((11, 15), paper.dimensions());
+    assert_eq!(
+        vec![(11, 7), (5, 7)],
+        paper.fold_all(&manual.instructions).unwrap()
+    );
+    let coordinates = paper.dots();
+    assert_eq!("#####\n#...#\n#...#\n#...#\n#####\n", render(coordinates));
+    assert_eq!(
+        "Unknown letter 1:\n#####\n#...#\n#...#\n#...#\n#####\n.....\n",
+        read_letters(coordinates).unwrap_err().to_string()
+    );
+}
+
+#[test]
+fn test_read_letters() {
+    let dots = |text: &[&str]| -> HashSet<(u32, u32)> {
+        (0..)
+            .zip(text)
+            .flat_map(|(y, row)| {
+                (0..)
+                    .zip(row.chars())
+                    .filter(|(_, char)| *char == '#')
+                    .map(move |(x, _)| (x, y))
+            })
+            .collect()
+    };
+    let all: Vec<String> = (0..6)
+        .map(|y| {
+            LETTERS
+                .iter()
+                .map(|(_, rows)| format!("{}.", rows[y]))
+                .collect()
+        })
+        .collect();
+    let all: Vec<&str> = all.iter().map(String::as_str).collect();
+    assert_eq!("ABCEFGHIJKLOPRSUZ", read_letters(&dots(&all)).unwrap());
 
-    for instuction in &manual.instructions {
-        coordinates = coordinates
+    // an H whose lower half is mirrored below the fold along y=6
+    let hi = ["#..#", "#..#", "####", "#..#", "#..#", "#..#"];
+    let mut input: Vec<String> = dots(&hi[..3])
+        .iter()
+        .map(|(x, y)| format!("{x},{y}"))
+        .collect();
+    input.extend(
+        dots(&hi[3..])
             .iter()
-            .map(|c| instuction.variant.fold_dot(*c, instuction.val))
-            .collect();
-    }
+            .map(|(x, y)| format!("{x},{}", 12 - 3 - y)),
+    );
+    let manual = generator(&format!("{}\n\nfold along y=6", input.join("\n")));
+    assert_eq!("H", part_2(&manual));
 
-    let x_max = coordinates.iter().map(|(x, _y)| *x).max().unwrap();
-    let y_max = coordinates.iter().map(|(_x, y)| *y).max().unwrap();
-    for y in 0..=y_max {
-        for x in 0..=x_max {
-            if coordinates.contains(&(x, y)) {
-                print!("#");
-            } else {
-                print!(" ");
-            }
-        }
-        println!("");
-    }
+    let unknown = dots(&["#..#", ".##.", "#..#", ".##.", "#..#", ".##."]);
+    assert_eq!(
+        "Unknown letter 1:\n#..#.\n.##..\n#..#.\n.##..\n#..#.\n.##..\n",
+        read_letters(&unknown).unwrap_err().to_string()
+    );
+    assert!(read_letters(&dots(&["#", "#", "#", "#", "#", "#", "#"])).is_err());
+}
+
+#[test]
+fn test_paper() {
+    let manual = generator("0,0\n1,0\n7,1\n\nfold along x=2\nfold along y=1");
+    let mut paper = Paper::new(&manual.dots);
+    assert_eq!((8, 2), paper.dimensions());
+    // the right part is longer, so the left one ends up on the right
+    paper.fold(&manual.instructions[0]).unwrap();
+    assert_eq!((5, 2), paper.dimensions());
+    assert_eq!(HashSet::from([(3, 0), (4, 0), (0, 1)]), *paper.dots());
+    assert_eq!(
+        "Dot 0,1 lies on the line of fold along y=1",
+        paper.fold(&manual.instructions[1]).unwrap_err().to_string()
+    );
 
-    42
+    // a fold line past the last dot folds nothing over, the paper reaches up to it
+    let manual = generator("0,0\n2,1\n\nfold along y=4\nfold along x=1");
+    let mut paper = Paper::new(&manual.dots);
+    assert_eq!(
+        vec![(3, 4), (1, 4)],
+        paper.fold_all(&manual.instructions).unwrap()
+    );
+    assert_eq!(HashSet::from([(0, 0), (0, 1)]), *paper.dots());
 }
@@ -1,105 +1,425 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::{self, Display, Formatter},
+    io::{BufReader, Bytes, Error, ErrorKind, Read},
+    marker::PhantomData,
+    str::FromStr,
+};
+
 use itertools::Itertools;
+use num_bigint::BigUint;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Packet {
     packet_version: u8,
     content: Content,
 }
 
 impl Packet {
+    pub fn version(&self) -> u8 {
+        self.packet_version
+    }
+
     pub fn get_value(&self) -> u64 {
-        let val = match &self.content {
-            Content::Sum(others) => others.iter().map(|o| o.get_value()).sum(),
-            Content::Product(others) => others
-                .iter()
-                .map(|o| o.get_value())
-                .reduce(|a, i| a * i)
-                .unwrap(),
-            Content::Minimum(others) => others.iter().map(|o| o.get_value()).min().unwrap(),
-            Content::Maximum(others) => others.iter().map(|o| o.get_value()).max().unwrap(),
-            Content::Literal(content) => calc_lit(content),
-            Content::GT(others) => {
-                if others[0].get_value() > others[1].get_value() {
-                    1
-                } else {
-                    0
-                }
-            }
-            Content::LT(others) => {
-                if others[0].get_value() < others[1].get_value() {
-                    1
-                } else {
-                    0
-                }
-            }
-            Content::EQ(others) => {
-                if others[0].get_value() == others[1].get_value() {
-                    1
-                } else {
-                    0
+        self.evaluate().expect("could not evaluate packet")
+    }
+
+    /// Evaluates the expression with checked arithmetic in `N`, naming the
+    /// path of sub-packet indices to the packet that failed.
+    pub fn evaluate<N: Number>(&self) -> Result<N, Error> {
+        self.fold(&mut Evaluator(PhantomData))
+    }
+
+    /// Folds the tree bottom up, handing every operator the results of its
+    /// sub-packets.
+    pub fn fold<V: PacketVisitor>(&self, visitor: &mut V) -> V::Output {
+        self.fold_at(visitor, &mut Vec::new())
+    }
+
+    fn fold_at<V: PacketVisitor>(&self, visitor: &mut V, path: &mut Vec<usize>) -> V::Output {
+        match &self.content {
+            Content::Literal(groups) => visitor.literal(self, groups, path),
+            Content::Operator(op, others) => {
+                let mut results = Vec::with_capacity(others.len());
+                for (idx, other) in others.iter().enumerate() {
+                    path.push(idx);
+                    results.push(other.fold_at(visitor, path));
+                    path.pop();
                 }
+                visitor.operator(self, *op, results, path)
             }
+        }
+    }
+}
+
+/// Visits every packet of a tree, see `Packet::fold`. `path` holds the
+/// sub-packet indices from the root, so its length is the depth.
+pub trait PacketVisitor {
+    type Output;
+
+    fn literal(&mut self, packet: &Packet, groups: &[u8], path: &[usize]) -> Self::Output;
+
+    fn operator(
+        &mut self,
+        packet: &Packet,
+        op: Operator,
+        others: Vec<Self::Output>,
+        path: &[usize],
+    ) -> Self::Output;
+}
+
+struct Evaluator<N>(PhantomData<N>);
+
+impl<N: Number> PacketVisitor for Evaluator<N> {
+    type Output = Result<N, Error>;
+
+    fn literal(&mut self, _: &Packet, groups: &[u8], path: &[usize]) -> Self::Output {
+        calc_lit(groups).ok_or_else(|| eval_error(path, "Literal overflows"))
+    }
+
+    fn operator(
+        &mut self,
+        _: &Packet,
+        op: Operator,
+        others: Vec<Self::Output>,
+        path: &[usize],
+    ) -> Self::Output {
+        let values = others.into_iter().collect::<Result<Vec<N>, Error>>()?;
+        if !op.arity_ok(values.len()) {
+            let what = format!("Operator with {} sub-packets", values.len());
+            return Err(eval_error(path, &what));
+        }
+
+        let mut values = values.into_iter();
+        let val = match op {
+            Operator::Sum => values
+                .try_fold(N::from_u8(0), |a, i| a.checked_add(&i))
+                .ok_or_else(|| eval_error(path, "Sum overflows"))?,
+            Operator::Product => values
+                .try_fold(N::from_u8(1), |a, i| a.checked_mul(&i))
+                .ok_or_else(|| eval_error(path, "Product overflows"))?,
+            Operator::Minimum => values.min().unwrap(),
+            Operator::Maximum => values.max().unwrap(),
+            Operator::GT => N::from_u8((values.next() > values.next()) as u8),
+            Operator::LT => N::from_u8((values.next() < values.next()) as u8),
+            Operator::EQ => N::from_u8((values.next() == values.next()) as u8),
         };
-        // // println!("get value for {:?} returns {}", self, val);
-        val
+        Ok(val)
+    }
+}
+
+fn eval_error(path: &[usize], what: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("{what} at packet {}", path_str(path)),
+    )
+}
+
+fn path_str(path: &[usize]) -> String {
+    format!("/{}", path.iter().join("/"))
+}
+
+struct VersionSum;
+
+impl PacketVisitor for VersionSum {
+    type Output = u32;
+
+    fn literal(&mut self, packet: &Packet, _: &[u8], _: &[usize]) -> u32 {
+        packet.version() as u32
     }
+
+    fn operator(&mut self, packet: &Packet, _: Operator, others: Vec<u32>, _: &[usize]) -> u32 {
+        packet.version() as u32 + others.iter().sum::<u32>()
+    }
+}
+
+struct MaxDepth;
+
+impl PacketVisitor for MaxDepth {
+    type Output = usize;
+
+    fn literal(&mut self, _: &Packet, _: &[u8], path: &[usize]) -> usize {
+        path.len()
+    }
+
+    fn operator(&mut self, _: &Packet, _: Operator, others: Vec<usize>, path: &[usize]) -> usize {
+        others.into_iter().max().unwrap_or(path.len())
+    }
+}
+
+/// Counts packets by type id, and literals by their value.
+#[derive(Default)]
+struct Census {
+    types: BTreeMap<u8, usize>,
+    literals: BTreeMap<BigUint, usize>,
+}
+
+impl PacketVisitor for Census {
+    type Output = ();
+
+    fn literal(&mut self, _: &Packet, groups: &[u8], _: &[usize]) {
+        *self.types.entry(LITERAL_TYPE_ID).or_default() += 1;
+        let value = calc_lit(groups).unwrap();
+        *self.literals.entry(value).or_default() += 1;
+    }
+
+    fn operator(&mut self, _: &Packet, op: Operator, _: Vec<()>, _: &[usize]) {
+        *self.types.entry(op.type_id()).or_default() += 1;
+    }
+}
+
+/// Number of packets per type id.
+pub fn count_by_type(packet: &Packet) -> BTreeMap<u8, usize> {
+    let mut census = Census::default();
+    packet.fold(&mut census);
+    census.types
+}
+
+/// Depth of the deepest packet, the outermost one being at depth 0.
+pub fn max_depth(packet: &Packet) -> usize {
+    packet.fold(&mut MaxDepth)
+}
+
+/// How often each literal value occurs.
+pub fn literal_distribution(packet: &Packet) -> BTreeMap<BigUint, usize> {
+    let mut census = Census::default();
+    packet.fold(&mut census);
+    census.literals
 }
 
-fn calc_lit(values: &[u8]) -> u64 {
-    // println!("lit: {:?}", values);
-    let res = values
-        .iter()
-        .rev()
-        .map(|v| *v as u64)
-        .enumerate()
-        .map(|(p, v)| v * 16u64.pow((p as u64).try_into().unwrap()))
-        .sum();
-    // println!("res: {res}");
-    res
+/// Numeric backend for `Packet::evaluate`.
+pub trait Number: Clone + Ord {
+    fn from_u8(value: u8) -> Self;
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+}
+
+impl Number for u64 {
+    fn from_u8(value: u8) -> Self {
+        value as u64
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        u64::checked_add(*self, *other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        u64::checked_mul(*self, *other)
+    }
 }
 
-#[derive(Clone, Debug)]
-enum TypeId {
+impl Number for u128 {
+    fn from_u8(value: u8) -> Self {
+        value as u128
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        u128::checked_add(*self, *other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        u128::checked_mul(*self, *other)
+    }
+}
+
+impl Number for BigUint {
+    fn from_u8(value: u8) -> Self {
+        BigUint::from(value)
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        Some(self * other)
+    }
+}
+
+fn calc_lit<N: Number>(values: &[u8]) -> Option<N> {
+    let base = N::from_u8(16);
+    values.iter().try_fold(N::from_u8(0), |acc, v| {
+        acc.checked_mul(&base)?.checked_add(&N::from_u8(*v))
+    })
+}
+
+const LITERAL_TYPE_ID: u8 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operator {
     Sum,
     Product,
     Minimum,
     Maximum,
-    Literal,
     GT,
     LT,
     EQ,
 }
 
-impl From<u8> for TypeId {
-    fn from(byte: u8) -> Self {
-        match byte {
-            0 => TypeId::Sum,
-            1 => TypeId::Product,
-            2 => TypeId::Minimum,
-            3 => TypeId::Maximum,
-            4 => TypeId::Literal,
-            5 => TypeId::GT,
-            6 => TypeId::LT,
-            7 => TypeId::EQ,
-            _ => unreachable!(),
+impl Operator {
+    const ALL: [Operator; 7] = [
+        Operator::Sum,
+        Operator::Product,
+        Operator::Minimum,
+        Operator::Maximum,
+        Operator::GT,
+        Operator::LT,
+        Operator::EQ,
+    ];
+
+    /// The operator for a packet type id, `None` for literals.
+    fn from_type_id(type_id: u8) -> Option<Operator> {
+        Operator::ALL.into_iter().find(|op| op.type_id() == type_id)
+    }
+
+    fn type_id(self) -> u8 {
+        match self {
+            Operator::Sum => 0,
+            Operator::Product => 1,
+            Operator::Minimum => 2,
+            Operator::Maximum => 3,
+            Operator::GT => 5,
+            Operator::LT => 6,
+            Operator::EQ => 7,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Operator::Sum => "+",
+            Operator::Product => "*",
+            Operator::Minimum => "min",
+            Operator::Maximum => "max",
+            Operator::GT => ">",
+            Operator::LT => "<",
+            Operator::EQ => "=",
+        }
+    }
+
+    fn arity_ok(self, count: usize) -> bool {
+        match self {
+            Operator::GT | Operator::LT | Operator::EQ => count == 2,
+            _ => count > 0,
         }
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 enum Content {
-    Sum(Vec<Packet>),
-    Product(Vec<Packet>),
-    Minimum(Vec<Packet>),
-    Maximum(Vec<Packet>),
     Literal(Vec<u8>),
-    GT(Vec<Packet>),
-    LT(Vec<Packet>),
-    EQ(Vec<Packet>),
+    Operator(Operator, Vec<Packet>),
+}
+
+/// Renders the expression as s-expression, e.g. `(* (+ 1 3) (min 7 8 9))`,
+/// or in infix notation with `{:#}`, e.g. `((1 + 3) * min(7, 8, 9))`.
+impl Display for Packet {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let (op, others) = match &self.content {
+            Content::Literal(groups) => {
+                return write!(f, "{}", calc_lit::<BigUint>(groups).unwrap())
+            }
+            Content::Operator(op, others) => (*op, others),
+        };
+        if !f.alternate() {
+            write!(f, "({}", op.symbol())?;
+            for other in others {
+                write!(f, " {other}")?;
+            }
+            return write!(f, ")");
+        }
+
+        let separator = match op {
+            Operator::Minimum | Operator::Maximum => {
+                write!(f, "{}", op.symbol())?;
+                ", ".to_string()
+            }
+            Operator::EQ => " == ".to_string(),
+            _ => format!(" {} ", op.symbol()),
+        };
+        write!(f, "(")?;
+        for (idx, other) in others.iter().enumerate() {
+            if idx > 0 {
+                write!(f, "{separator}")?;
+            }
+            write!(f, "{other:#}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Parses the s-expression syntax of `Display`. The expression carries no
+/// versions, so every packet gets version 0.
+impl FromStr for Packet {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let spaced = s.replace('(', " ( ").replace(')', " ) ");
+        let mut tokens = spaced.split_whitespace().peekable();
+        let packet = parse_expression(&mut tokens)?;
+        if let Some(token) = tokens.next() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Unexpected {token} after expression in {s}"),
+            ));
+        }
+        Ok(packet)
+    }
+}
+
+fn parse_expression<'a, I>(tokens: &mut std::iter::Peekable<I>) -> Result<Packet, Error>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let unexpected = |what: &str| Error::new(ErrorKind::InvalidData, format!("Unexpected {what}"));
+    let token = tokens
+        .next()
+        .ok_or_else(|| unexpected("end of expression"))?;
+    if token != "(" {
+        let value: BigUint = token
+            .parse()
+            .map_err(|_| unexpected(&format!("token {token}")))?;
+        return Ok(Packet {
+            packet_version: 0,
+            content: Content::Literal(lit_groups(&value)),
+        });
+    }
+
+    let symbol = tokens
+        .next()
+        .ok_or_else(|| unexpected("end of expression"))?;
+    let op = Operator::ALL
+        .into_iter()
+        .find(|op| op.symbol() == symbol)
+        .ok_or_else(|| unexpected(&format!("operator {symbol}")))?;
+    let mut others = Vec::new();
+    while tokens.peek() != Some(&")") {
+        others.push(parse_expression(tokens)?);
+    }
+    tokens.next();
+
+    if !op.arity_ok(others.len()) {
+        return Err(unexpected(&format!(
+            "{} operands for {symbol}",
+            others.len()
+        )));
+    }
+    Ok(Packet {
+        packet_version: 0,
+        content: Content::Operator(op, others),
+    })
+}
+
+fn lit_groups(value: &BigUint) -> Vec<u8> {
+    value.to_radix_be(16)
 }
 
 pub fn generator(input: &str) -> Packet {
-    let input: Vec<u8> = input.chars().tuples().map(&from_hex).collect();
+    let digits = input.trim().chars().collect_vec();
+    // an odd number of digits gets a zero nibble appended instead of losing the last one
+    let input: Vec<u8> = digits
+        .chunks(2)
+        .map(|pair| from_hex((pair[0], *pair.get(1).unwrap_or(&'0'))))
+        .collect();
     parse_packet(&input, 0, &mut 0)
 }
 
@@ -108,7 +428,7 @@ fn parse_packet(val: &[u8], offset: u8, global_offset: &mut u64) -> Packet {
     let aligned_header = if offset == 0 {
         val[0]
     } else {
-        let mut aligned_header = to_u16(val[..2].try_into().expect("eh"));
+        let mut aligned_header = to_u16(&padded(val));
         aligned_header <<= offset;
         aligned_header >>= 8;
         aligned_header as u8
@@ -116,7 +436,7 @@ fn parse_packet(val: &[u8], offset: u8, global_offset: &mut u64) -> Packet {
     // // println!("aligned header:\t{:08b}", aligned_header);
     let packet_version = aligned_header >> 5;
     // // // println!("type_id: {:#08b}",  (aligned_header << 3) >> 5);
-    let packet_type = TypeId::from((aligned_header << 3) >> 5);
+    let packet_type = Operator::from_type_id((aligned_header << 3) >> 5);
     // // // println!("type: {:?}", packet_type);
 
     let offset = offset + 6;
@@ -125,24 +445,15 @@ fn parse_packet(val: &[u8], offset: u8, global_offset: &mut u64) -> Packet {
     let byte_offset = (offset / 8) as usize;
 
     let content = match packet_type {
-        TypeId::Sum => Content::Sum(parse_other(&val[byte_offset..], bit_offset, global_offset)),
-        TypeId::Product => {
-            Content::Product(parse_other(&val[byte_offset..], bit_offset, global_offset))
-        }
-        TypeId::Minimum => {
-            Content::Minimum(parse_other(&val[byte_offset..], bit_offset, global_offset))
-        }
-        TypeId::Maximum => {
-            Content::Maximum(parse_other(&val[byte_offset..], bit_offset, global_offset))
-        }
-        TypeId::Literal => Content::Literal(parse_literal(
+        Some(op) => Content::Operator(
+            op,
+            parse_other(&val[byte_offset..], bit_offset, global_offset),
+        ),
+        None => Content::Literal(parse_literal(
             &val[byte_offset..],
             bit_offset,
             global_offset,
         )),
-        TypeId::GT => Content::GT(parse_other(&val[byte_offset..], bit_offset, global_offset)),
-        TypeId::LT => Content::LT(parse_other(&val[byte_offset..], bit_offset, global_offset)),
-        TypeId::EQ => Content::EQ(parse_other(&val[byte_offset..], bit_offset, global_offset)),
     };
 
     Packet {
@@ -169,9 +480,9 @@ fn parse_other(val: &[u8], offset: u8, global_offset: &mut u64) -> Vec<Packet> {
     // // // println!("global offset: {global_offset}");
     let start_offset = *global_offset;
     let pck = if offset == 0 {
-        to_u16(val[..2].try_into().expect("eh"))
+        to_u16(&padded(val))
     } else {
-        let with_next = to_u32(val[..4].try_into().expect("eh"));
+        let with_next = to_u32(&padded(val));
         ((with_next << offset) >> 16) as u16
     };
     // // println!("other pck:\t{:016b}", pck);
@@ -222,7 +533,7 @@ fn parse_literal(val: &[u8], offset: u8, global_offset: &mut u64) -> Vec<u8> {
     let pck = if offset < 4 {
         val[0] << offset
     } else {
-        let with_next = to_u16(val[..2].try_into().expect("eh"));
+        let with_next = to_u16(&padded(val));
         ((with_next << offset) >> 8) as u8
     };
     // // println!("literal pck:\t{:08b}", pck);
@@ -242,6 +553,14 @@ fn parse_literal(val: &[u8], offset: u8, global_offset: &mut u64) -> Vec<u8> {
     res_vec
 }
 
+/// Reads the next `N` bytes, filling up with zeros past the end of the transmission.
+fn padded<const N: usize>(val: &[u8]) -> [u8; N] {
+    let mut bytes = [0; N];
+    let len = val.len().min(N);
+    bytes[..len].copy_from_slice(&val[..len]);
+    bytes
+}
+
 fn to_u32(val: &[u8; 4]) -> u32 {
     ((val[0] as u32) << 24) + ((val[1] as u32) << 16) + ((val[2] as u32) << 8) + (val[3] as u32)
 }
@@ -251,28 +570,13 @@ fn to_u16(val: &[u8; 2]) -> u16 {
 }
 
 pub fn part_1(value: &Packet) -> u32 {
-    get_version_sum(value)
+    value.fold(&mut VersionSum)
 }
 
 pub fn part_2(value: &Packet) -> u64 {
     value.get_value()
 }
 
-fn get_version_sum(packet: &Packet) -> u32 {
-    let sum = packet.packet_version as u32;
-    let ver_sum = match &packet.content {
-        Content::Literal(_) => 0,
-        Content::Sum(others)
-        | Content::Product(others)
-        | Content::Minimum(others)
-        | Content::Maximum(others)
-        | Content::GT(others)
-        | Content::LT(others)
-        | Content::EQ(others) => others.iter().map(|p| get_version_sum(p)).sum(),
-    };
-    sum + ver_sum
-}
-
 fn from_hex((a, b): (char, char)) -> u8 {
     (hex_val(a) << 4) + hex_val(b)
 }
@@ -295,10 +599,425 @@ fn hex_val(hex_val: char) -> u8 {
         'D' => 13,
         'E' => 14,
         'F' => 15,
+        'a' => 10,
+        'b' => 11,
+        'c' => 12,
+        'd' => 13,
+        'e' => 14,
+        'f' => 15,
         _ => unreachable!(),
     }
 }
 
+/// Which length header `encode` writes for operator packets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LengthType {
+    /// Count header if the sub-packets fit into it, total length otherwise.
+    Auto,
+    /// Length type 0: 15 bit total length of the sub-packets in bits.
+    TotalBits,
+    /// Length type 1: 11 bit number of sub-packets.
+    SubPacketCount,
+}
+
+pub fn encode(packet: &Packet, length_type: LengthType) -> Result<String, Error> {
+    let mut bits = Vec::new();
+    encode_packet(packet, length_type, &mut bits)?;
+    // the decoder reads whole bytes, so pad to a full one
+    while bits.len() % 8 != 0 {
+        bits.push(false);
+    }
+    Ok(bits_to_hex(&bits))
+}
+
+fn bits_to_hex(bits: &[bool]) -> String {
+    bits.chunks(4)
+        .map(|nibble| {
+            let value = nibble.iter().fold(0, |acc, bit| (acc << 1) + *bit as u8);
+            // a short last chunk is padded with zeros
+            to_hex(value << (4 - nibble.len()))
+        })
+        .collect()
+}
+
+fn encode_packet(
+    packet: &Packet,
+    length_type: LengthType,
+    bits: &mut Vec<bool>,
+) -> Result<(), Error> {
+    if packet.packet_version > 7 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Version {} does not fit into 3 bits", packet.packet_version),
+        ));
+    }
+    push_bits(bits, packet.packet_version as u64, 3);
+
+    let (op, others) = match &packet.content {
+        Content::Literal(groups) => return encode_literal(groups, bits),
+        Content::Operator(op, others) => (op, others),
+    };
+    push_bits(bits, op.type_id() as u64, 3);
+
+    let mut sub_bits = Vec::new();
+    for other in others {
+        encode_packet(other, length_type, &mut sub_bits)?;
+    }
+
+    let length_type = match length_type {
+        LengthType::Auto if others.len() < 1 << 11 => LengthType::SubPacketCount,
+        LengthType::Auto => LengthType::TotalBits,
+        requested => requested,
+    };
+    match length_type {
+        LengthType::TotalBits if sub_bits.len() < 1 << 15 => {
+            push_bits(bits, 0, 1);
+            push_bits(bits, sub_bits.len() as u64, 15);
+        }
+        LengthType::SubPacketCount if others.len() < 1 << 11 => {
+            push_bits(bits, 1, 1);
+            push_bits(bits, others.len() as u64, 11);
+        }
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "{} sub-packets with {} bits do not fit into a {:?} header",
+                    others.len(),
+                    sub_bits.len(),
+                    length_type
+                ),
+            ))
+        }
+    }
+    bits.append(&mut sub_bits);
+    Ok(())
+}
+
+fn encode_literal(groups: &[u8], bits: &mut Vec<bool>) -> Result<(), Error> {
+    if groups.is_empty() || groups.iter().any(|group| *group > 15) {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Literal groups {:?} are not a list of nibbles", groups),
+        ));
+    }
+    push_bits(bits, LITERAL_TYPE_ID as u64, 3);
+    for (idx, group) in groups.iter().enumerate() {
+        let not_last = idx + 1 < groups.len();
+        push_bits(bits, not_last as u64, 1);
+        push_bits(bits, *group as u64, 4);
+    }
+    Ok(())
+}
+
+fn push_bits(bits: &mut Vec<bool>, value: u64, width: u8) {
+    for shift in (0..width).rev() {
+        bits.push((value >> shift) & 1 == 1);
+    }
+}
+
+fn to_hex(nibble: u8) -> char {
+    std::char::from_digit(nibble as u32, 16)
+        .expect("not a nibble")
+        .to_ascii_uppercase()
+}
+
+/// Annotates every field of the transmission with its bit offset and raw
+/// bits, indented by the depth of the packet it belongs to.
+pub fn disassemble(input: &str) -> Result<String, Error> {
+    let mut bits = Vec::new();
+    for (idx, digit) in input.trim().chars().enumerate() {
+        let nibble = digit.to_digit(16).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid hex digit {digit} at {idx}"),
+            )
+        })?;
+        push_bits(&mut bits, nibble as u64, 4);
+    }
+
+    let mut disassembler = Disassembler {
+        bits: &bits,
+        pos: 0,
+        lines: Vec::new(),
+    };
+    disassembler.packet(&mut Vec::new())?;
+    let padding = &bits[disassembler.pos..];
+    if !padding.is_empty() {
+        let label = if padding.contains(&true) {
+            "padding, NOT ZERO"
+        } else {
+            "padding"
+        };
+        disassembler.line(
+            disassembler.pos,
+            0,
+            &format!("{:<16} {label}", raw_bits(padding)),
+        );
+    }
+    Ok(disassembler.lines.join("\n"))
+}
+
+struct Disassembler<'a> {
+    bits: &'a [bool],
+    pos: usize,
+    lines: Vec<String>,
+}
+
+impl Disassembler<'_> {
+    fn line(&mut self, pos: usize, depth: usize, text: &str) {
+        self.lines.push(format!(
+            "{pos:>5}  {:indent$}{text}",
+            "",
+            indent = 2 * depth
+        ));
+    }
+
+    fn field(
+        &mut self,
+        width: usize,
+        depth: usize,
+        describe: impl FnOnce(u64) -> String,
+    ) -> Result<u64, Error> {
+        let end = self.pos + width;
+        if end > self.bits.len() {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                format!(
+                    "Transmission ends inside the {width} bit field at bit {}",
+                    self.pos
+                ),
+            ));
+        }
+        let raw = &self.bits[self.pos..end];
+        let value = raw.iter().fold(0, |acc, bit| (acc << 1) + *bit as u64);
+        let text = format!("{:<16} {}", raw_bits(raw), describe(value));
+        self.line(self.pos, depth, &text);
+        self.pos = end;
+        Ok(value)
+    }
+
+    fn packet(&mut self, path: &mut Vec<usize>) -> Result<(), Error> {
+        let header = format!("-- packet {} --", path_str(path));
+        self.line(self.pos, path.len(), &header);
+        let depth = path.len() + 1;
+
+        self.field(3, depth, |v| format!("version {v}"))?;
+        let type_id = self.field(3, depth, |v| match Operator::from_type_id(v as u8) {
+            Some(op) => format!("type {v} ({op:?})"),
+            None => format!("type {v} (Literal)"),
+        })?;
+        if type_id == LITERAL_TYPE_ID as u64 {
+            let mut groups = Vec::new();
+            loop {
+                let group = self.field(5, depth, |v| match v >> 4 {
+                    1 => format!("group {}, more follow", v & 0xF),
+                    _ => format!("group {v}, last"),
+                })?;
+                groups.push((group & 0xF) as u8);
+                if group >> 4 == 0 {
+                    break;
+                }
+            }
+            let value = calc_lit::<BigUint>(&groups).unwrap();
+            if let Some(last) = self.lines.last_mut() {
+                last.push_str(&format!(" => {value}"));
+            }
+            return Ok(());
+        }
+
+        let length_type = self.field(1, depth, |v| format!("length type {v}"))?;
+        if length_type == 0 {
+            let length = self.field(15, depth, |v| format!("total length {v} bits"))?;
+            let end = self.pos + length as usize;
+            let mut idx = 0;
+            while self.pos < end {
+                path.push(idx);
+                self.packet(path)?;
+                path.pop();
+                idx += 1;
+            }
+            if self.pos != end {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Sub-packets end at bit {} instead of {end} at packet {}",
+                        self.pos,
+                        path_str(path)
+                    ),
+                ));
+            }
+        } else {
+            let count = self.field(11, depth, |v| format!("{v} sub-packets"))?;
+            for idx in 0..count as usize {
+                path.push(idx);
+                self.packet(path)?;
+                path.pop();
+            }
+        }
+        Ok(())
+    }
+}
+
+fn raw_bits(bits: &[bool]) -> String {
+    bits.iter()
+        .map(|bit| if *bit { '1' } else { '0' })
+        .collect()
+}
+
+/// Decodes consecutive top-level packets from `reader`. Transmissions are
+/// separated by whitespace, and a transmission may hold several packets back
+/// to back. Zero bits left at the end of a transmission are padding.
+pub fn decode<R: Read>(reader: R) -> PacketStream<R> {
+    PacketStream {
+        bytes: BufReader::new(reader).bytes(),
+        offset: 0,
+        bits: VecDeque::new(),
+        consumed: 0,
+        done: false,
+    }
+}
+
+pub struct PacketStream<R> {
+    bytes: Bytes<BufReader<R>>,
+    /// index of the next byte to read
+    offset: usize,
+    /// bits read from the current transmission but not yet decoded
+    bits: VecDeque<bool>,
+    /// bits decoded from the current transmission
+    consumed: usize,
+    done: bool,
+}
+
+enum Fill {
+    Nibble,
+    Separator,
+    Eof,
+}
+
+impl<R: Read> PacketStream<R> {
+    fn fill(&mut self) -> Result<Fill, Error> {
+        let byte = match self.bytes.next() {
+            None => return Ok(Fill::Eof),
+            Some(byte) => byte?,
+        };
+        self.offset += 1;
+        if byte.is_ascii_whitespace() {
+            return Ok(Fill::Separator);
+        }
+        let nibble = (byte as char).to_digit(16).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Invalid hex digit {:?} at byte {}",
+                    byte as char,
+                    self.offset - 1
+                ),
+            )
+        })?;
+        for shift in (0..4).rev() {
+            self.bits.push_back((nibble >> shift) & 1 == 1);
+        }
+        Ok(Fill::Nibble)
+    }
+
+    fn take(&mut self, width: usize) -> Result<u64, Error> {
+        while self.bits.len() < width {
+            // the end of the data lies at the offset, a separator right before it
+            let end = match self.fill()? {
+                Fill::Nibble => continue,
+                Fill::Separator => self.offset - 1,
+                Fill::Eof => self.offset,
+            };
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                format!("Transmission ends inside a packet at byte {end}"),
+            ));
+        }
+        self.consumed += width;
+        Ok(self
+            .bits
+            .drain(..width)
+            .fold(0, |acc, bit| (acc << 1) + bit as u64))
+    }
+
+    fn packet(&mut self) -> Result<Packet, Error> {
+        let packet_version = self.take(3)? as u8;
+        let op = match Operator::from_type_id(self.take(3)? as u8) {
+            Some(op) => op,
+            None => {
+                let mut groups = Vec::new();
+                loop {
+                    let group = self.take(5)?;
+                    groups.push((group & 0xF) as u8);
+                    if group >> 4 == 0 {
+                        break;
+                    }
+                }
+                return Ok(Packet {
+                    packet_version,
+                    content: Content::Literal(groups),
+                });
+            }
+        };
+
+        let mut others = Vec::new();
+        if self.take(1)? == 0 {
+            let end = self.take(15)? as usize + self.consumed;
+            while self.consumed < end {
+                others.push(self.packet()?);
+            }
+            if self.consumed != end {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Sub-packets overrun their length at byte {}", self.offset),
+                ));
+            }
+        } else {
+            for _ in 0..self.take(11)? {
+                others.push(self.packet()?);
+            }
+        }
+        Ok(Packet {
+            packet_version,
+            content: Content::Operator(op, others),
+        })
+    }
+
+    /// Skips padding and separators up to the next bit that can start a
+    /// packet. Returns `false` once the reader is exhausted.
+    fn seek_packet(&mut self) -> Result<bool, Error> {
+        while !self.bits.contains(&true) {
+            match self.fill()? {
+                Fill::Nibble => (),
+                Fill::Separator => {
+                    self.bits.clear();
+                    self.consumed = 0;
+                }
+                Fill::Eof => return Ok(false),
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read> Iterator for PacketStream<R> {
+    type Item = Result<Packet, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let packet = match self.seek_packet() {
+            Ok(false) => None,
+            Ok(true) => Some(self.packet()),
+            Err(err) => Some(Err(err)),
+        };
+        self.done = !matches!(packet, Some(Ok(_)));
+        packet
+    }
+}
+
 #[test]
 fn test_bitshifts() {
     let val: Vec<u8> = vec![56];
@@ -336,3 +1055,279 @@ fn test_2() {
     assert_eq!(0, part_2(&generator(&"9C005AC2F8F0")));
     assert_eq!(1, part_2(&generator(&"9C0141080250320F1802104A08")));
 }
+
+#[test]
+fn test_encode() {
+    for hex in [
+        "8A004A801A8002F478",
+        "620080001611562C8802118E34",
+        "C0015000016115A2E0802F182340",
+        "A0016C880162017C3686B18A3D4780",
+        "9C0141080250320F1802104A08",
+    ] {
+        let packet = generator(hex);
+        let encoded = encode(&packet, LengthType::Auto).unwrap();
+        assert_eq!(packet, generator(&encoded));
+        assert_eq!(part_2(&packet), part_2(&generator(&encoded)));
+    }
+    assert_eq!(
+        "D2FE28",
+        encode(&generator("D2FE28"), LengthType::Auto).unwrap()
+    );
+    assert_eq!(
+        "38006F45291200",
+        encode(&generator("38006F45291200"), LengthType::TotalBits).unwrap()
+    );
+    assert_eq!(
+        "EE00D40C823060",
+        encode(&generator("EE00D40C823060"), LengthType::SubPacketCount).unwrap()
+    );
+}
+
+#[test]
+fn test_encode_invalid() {
+    let literal = |groups: Vec<u8>| Packet {
+        packet_version: 0,
+        content: Content::Literal(groups),
+    };
+    assert!(encode(&literal(vec![]), LengthType::Auto).is_err());
+    assert!(encode(&literal(vec![16]), LengthType::Auto).is_err());
+
+    let wide = Packet {
+        packet_version: 0,
+        content: Content::Operator(Operator::Sum, vec![literal(vec![1]); 1 << 11]),
+    };
+    assert!(encode(&wide, LengthType::SubPacketCount).is_err());
+    assert_eq!(wide, generator(&encode(&wide, LengthType::Auto).unwrap()));
+}
+
+#[test]
+fn test_decode_short_tail() {
+    // operator header starting late in one of the last three bytes
+    let literal = |groups: Vec<u8>| Packet {
+        packet_version: 0,
+        content: Content::Literal(groups),
+    };
+    let packet = Packet {
+        packet_version: 0,
+        content: Content::Operator(
+            Operator::Sum,
+            vec![
+                Packet {
+                    packet_version: 0,
+                    content: Content::Operator(
+                        Operator::Sum,
+                        vec![
+                            literal(vec![0; 8]),
+                            literal(vec![0; 13]),
+                            literal(vec![0; 11]),
+                            literal(vec![0; 11]),
+                        ],
+                    ),
+                },
+                Packet {
+                    packet_version: 0,
+                    content: Content::Operator(Operator::Sum, vec![literal(vec![0])]),
+                },
+            ],
+        ),
+    };
+    let encoded = encode(&packet, LengthType::Auto).unwrap();
+    assert_eq!(packet, generator(&encoded));
+}
+
+#[cfg(test)]
+fn packet_strategy() -> impl proptest::strategy::Strategy<Value = Packet> {
+    use proptest::prelude::*;
+    let literal =
+        (0u8..8, prop::collection::vec(0u8..16, 1..20)).prop_map(|(version, groups)| Packet {
+            packet_version: version,
+            content: Content::Literal(groups),
+        });
+    literal.prop_recursive(4, 64, 5, |inner| {
+        (0u8..8, 0usize..7, prop::collection::vec(inner, 1..5)).prop_map(|(version, op, others)| {
+            Packet {
+                packet_version: version,
+                content: Content::Operator(Operator::ALL[op], others),
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn test_encode_roundtrip(packet in packet_strategy()) {
+        for length_type in [LengthType::Auto, LengthType::TotalBits, LengthType::SubPacketCount] {
+            let encoded = encode(&packet, length_type).unwrap();
+            proptest::prop_assert_eq!(&packet, &generator(&encoded));
+            let streamed = decode(encoded.as_bytes()).next().unwrap().unwrap();
+            proptest::prop_assert_eq!(&packet, &streamed);
+        }
+    }
+}
+
+#[test]
+fn test_display() {
+    let packet = generator("9C0141080250320F1802104A08");
+    assert_eq!("(= (+ 1 3) (* 2 2))", packet.to_string());
+    assert_eq!("((1 + 3) == (2 * 2))", format!("{packet:#}"));
+    assert_eq!("(min 7 8 9)", generator("880086C3E88112").to_string());
+    assert_eq!("max(7, 8, 9)", format!("{:#}", generator("CE00C43D881120")));
+    assert_eq!("2021", generator("D2FE28").to_string());
+}
+
+#[test]
+fn test_parse_expression() {
+    let packet: Packet = "(* (+ 1 3) (min 7 8 9))".parse().unwrap();
+    assert_eq!(28, packet.get_value());
+    assert_eq!("(* (+ 1 3) (min 7 8 9))", packet.to_string());
+    let encoded = encode(&packet, LengthType::Auto).unwrap();
+    assert_eq!(packet, generator(&encoded));
+
+    let packet: Packet = " ( >  2021 (max 15 16) ) ".parse().unwrap();
+    assert_eq!(1, packet.get_value());
+    assert_eq!(vec![7, 14, 5], lit_groups(&BigUint::from(2021u16)));
+    assert_eq!(vec![0], lit_groups(&BigUint::from(0u8)));
+
+    for invalid in [
+        "", "(+ 1 2", "(+)", "(< 1)", "(% 1 2)", "(+ 1 2))", "x", "(+ 1 -2)",
+    ] {
+        assert!(invalid.parse::<Packet>().is_err(), "{invalid}");
+    }
+}
+
+#[test]
+fn test_evaluate_overflow() {
+    let packet: Packet = "(+ 1 (* 4294967296 4294967296))".parse().unwrap();
+    let err = packet.evaluate::<u64>().unwrap_err();
+    assert_eq!("Product overflows at packet /1", err.to_string());
+    assert_eq!(1 << 64 | 1, packet.evaluate::<u128>().unwrap());
+    assert_eq!(
+        BigUint::from(1u128 << 64 | 1),
+        packet.evaluate::<BigUint>().unwrap()
+    );
+
+    let long_literal = Packet {
+        packet_version: 0,
+        content: Content::Literal(vec![15; 17]),
+    };
+    let packet = Packet {
+        packet_version: 0,
+        content: Content::Operator(Operator::Maximum, vec![generator("D2FE28"), long_literal]),
+    };
+    let err = packet.evaluate::<u64>().unwrap_err();
+    assert_eq!("Literal overflows at packet /1", err.to_string());
+    assert_eq!((1 << 68) - 1, packet.evaluate::<u128>().unwrap());
+    assert_eq!("(max 2021 295147905179352825855)", packet.to_string());
+    // expressions carry no versions, so the literal comes back as the version 0 packet it was
+    let parsed: Packet = packet.to_string().parse().unwrap();
+    assert_eq!(packet.to_string(), parsed.to_string());
+    assert_eq!(
+        Content::Literal(vec![15; 17]),
+        "295147905179352825855".parse::<Packet>().unwrap().content
+    );
+
+    let packet = Packet {
+        packet_version: 0,
+        content: Content::Operator(Operator::GT, vec![generator("D2FE28")]),
+    };
+    let err = packet.evaluate::<u64>().unwrap_err();
+    assert_eq!("Operator with 1 sub-packets at packet /", err.to_string());
+}
+
+#[test]
+fn test_disassemble() {
+    let expected = [
+        "    0  -- packet / --",
+        "    0    110              version 6",
+        "    3    100              type 4 (Literal)",
+        "    6    10111            group 7, more follow",
+        "   11    11110            group 14, more follow",
+        "   16    00101            group 5, last => 2021",
+        "   21  000              padding",
+    ]
+    .join("\n");
+    assert_eq!(expected, disassemble("D2FE28").unwrap());
+
+    let lines = disassemble("38006F45291200").unwrap();
+    assert!(lines.contains("    7    000000000011011  total length 27 bits\n"));
+    assert!(lines.contains("   33    -- packet /1 --\n"));
+    assert!(lines.contains("   44      00100            group 4, last => 20\n"));
+
+    let lines = disassemble("ee00d40c823061").unwrap();
+    assert!(lines.ends_with("   51  00001            padding, NOT ZERO"));
+
+    assert!(disassemble("D2FE2").is_err());
+    assert!(disassemble("D2FG28").is_err());
+}
+
+#[test]
+fn test_visitors() {
+    let packet: Packet = "(* (+ 1 3) (min 7 (max 3 8) 3))".parse().unwrap();
+    assert_eq!(3, max_depth(&packet));
+    assert_eq!(0, max_depth(&generator("D2FE28")));
+
+    let types = count_by_type(&packet);
+    assert_eq!(
+        vec![(0, 1), (1, 1), (2, 1), (3, 1), (4, 6)],
+        types.into_iter().collect_vec()
+    );
+
+    let literals = literal_distribution(&packet);
+    assert_eq!(Some(&3), literals.get(&BigUint::from(3u8)));
+    assert_eq!(Some(&1), literals.get(&BigUint::from(8u8)));
+    assert_eq!(4, literals.len());
+
+    assert_eq!(0, part_1(&packet));
+    assert_eq!(1 + 6 + 2, part_1(&generator("38006F45291200")));
+}
+
+#[test]
+fn test_decode() {
+    let input =
+        "8A004A801A8002F478\n620080001611562C8802118E34\r\n\n  c0015000016115a2e0802f182340 \n";
+    let sums = decode(input.as_bytes())
+        .map(|packet| part_1(&packet.unwrap()))
+        .collect_vec();
+    assert_eq!(vec![16, 12, 23], sums);
+
+    // two literals back to back, 2021 and 10 with version 6
+    let mut bits = Vec::new();
+    encode_packet(&generator("D2FE28"), LengthType::Auto, &mut bits).unwrap();
+    push_bits(&mut bits, 0b11010001010, 11);
+    let values = decode(bits_to_hex(&bits).as_bytes())
+        .map(|packet| packet.unwrap().get_value())
+        .collect_vec();
+    assert_eq!(vec![2021, 10], values);
+
+    // odd number of digits, the last one holds the end of the literal
+    let packet = Packet {
+        packet_version: 1,
+        content: Content::Literal(vec![1, 2, 3, 4]),
+    };
+    let mut bits = Vec::new();
+    encode_packet(&packet, LengthType::Auto, &mut bits).unwrap();
+    let hex = bits_to_hex(&bits);
+    assert_eq!(1, hex.len() % 2);
+    assert_eq!(packet, decode(hex.as_bytes()).next().unwrap().unwrap());
+    assert_eq!(packet, generator(&hex));
+
+    let mut stream = decode("D2FE28\nD2XE28".as_bytes());
+    assert_eq!(2021, stream.next().unwrap().unwrap().get_value());
+    let err = stream.next().unwrap().unwrap_err();
+    assert_eq!("Invalid hex digit 'X' at byte 9", err.to_string());
+    assert!(stream.next().is_none());
+
+    let err = decode("D2FE D2FE28".as_bytes())
+        .next()
+        .unwrap()
+        .unwrap_err();
+    assert_eq!(ErrorKind::UnexpectedEof, err.kind());
+    assert_eq!(
+        "Transmission ends inside a packet at byte 4",
+        err.to_string()
+    );
+
+    assert!(decode("  \n0000\n".as_bytes()).next().is_none());
+}
@@ -1,3 +1,12 @@
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    io::{Error, ErrorKind},
+    iter,
+};
+
+use fnv::FnvHashSet;
+use itertools::{Either, Itertools};
 use parse_display::{Display, FromStr};
 
 #[derive(Debug, Clone, Copy, Display, FromStr)]
@@ -9,71 +18,323 @@ pub struct TargetRectangle {
     y2: i32,
 }
 
+impl TargetRectangle {
+    /// The same area with `x1 <= x2` and `y1 <= y2`.
+    fn normalized(&self) -> TargetRectangle {
+        TargetRectangle {
+            x1: self.x1.min(self.x2),
+            x2: self.x1.max(self.x2),
+            y1: self.y1.min(self.y2),
+            y2: self.y1.max(self.y2),
+        }
+    }
+
+    fn contains(&self, (x, y): (i32, i32)) -> bool {
+        (self.x1..=self.x2).contains(&x) && (self.y1..=self.y2).contains(&y)
+    }
+}
+
 pub fn generator(input: &str) -> TargetRectangle {
     input.parse().unwrap()
 }
 
 pub fn part_1(input: &TargetRectangle) -> i32 {
-    let y_target = input.y1..input.y2;
-    let x_target = input.x1..input.x2;
-
-    // highest x is when it clears the whole range in two steps -> 2x-1 > max_x_target
-    let max_x = (input.x2 + 1) / 2;
-
-    for y_val in (0..=input.y1.abs()).rev() {
-        for n_val in y_val..input.x2 {
-            if y_target.contains(&y_at(y_val, n_val)) {
-                for x_val in 1..max_x {
-                    if x_target.contains(&x_at(x_val, n_val)) {
-                        return gauss(y_val);
-                    }
-                }
-            }
-        }
-    }
-    unreachable!();
+    solve(input)
+        .expect("no highest shot")
+        .keys()
+        .map(|(_, y_vel)| gauss((*y_vel).max(0)))
+        .max()
+        .unwrap()
 }
 
-
 #[test]
 pub fn test2() {
     let input = "target area: x=20..30, y=-10..-5";
     assert_eq!(112, part_2(&generator(&input)));
 }
 
-pub fn part_2(input: &TargetRectangle) -> i32 {
-    let y_target = input.y1..=input.y2;
-    let x_target = input.x1..=input.x2;
+pub fn part_2(input: &TargetRectangle) -> usize {
+    solve(input).expect("uncountable").len()
+}
 
-    let max_x = input.x2;
+/// Every initial velocity that puts the probe into the target area, with the
+/// steps (starting at 1) at which it is inside. Works for targets anywhere
+/// around the launcher, but fails if there are infinitely many velocities,
+/// which happens when the target contains y=0 and the probe can come to a
+/// stop in its x range.
+pub fn solve(input: &TargetRectangle) -> Result<BTreeMap<(i32, i32), Vec<i32>>, Error> {
+    let target = input.normalized();
+    let TargetRectangle { x1, x2, y1, y2 } = target;
+    let x_target = x1..=x2;
+    let y_target = y1..=y2;
 
-    let mut counter = 0;
+    // any faster shot overshoots with the first step
+    let x_vels = x1.min(0)..=x2.max(0);
+    if y_target.contains(&0)
+        && x_vels
+            .clone()
+            .any(|x_vel| x_target.contains(&x_at(x_vel, 1, x_vel.abs())))
+    {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Infinitely many shots end in {input}"),
+        ));
+    }
 
-    for y_val in input.y1..=input.y1.abs() {
-        'xloop: for x_val in 1..=max_x {
-            for n_val in 0..input.x2 {
-                if y_target.contains(&y_at(y_val, n_val)) {
-                    if x_target.contains(&x_at(x_val, n_val)) {
-                        counter += 1;
-                        continue 'xloop;
-                    }
-                }
+    // going up, a shot is at least as high as its first step, coming down it
+    // passes y=0 and then is lower than the negated velocity. If 0 is inside,
+    // x has to be still moving when the shot gets back to 0.
+    let mut max_y_vel = y1.abs().max(y2.abs());
+    if y_target.contains(&0) {
+        max_y_vel = max_y_vel.max(x1.abs()).max(x2.abs());
+    }
+    let y_vels = y1.min(0)..=max_y_vel;
+
+    let mut hits = BTreeMap::new();
+    for x_vel in x_vels {
+        for y_vel in y_vels.clone() {
+            let steps = flight(&ProbePhysics::default(), y1, (x_vel, y_vel))
+                .zip(1..)
+                .filter(|(position, _)| target.contains(*position))
+                .map(|(_, step)| step)
+                .collect_vec();
+            if !steps.is_empty() {
+                hits.insert((x_vel, y_vel), steps);
             }
         }
     }
-    counter
+    Ok(hits)
+}
+
+/// Gives up on shots that never come down.
+const MAX_STEPS: usize = 10_000;
+
+/// Gravity, drag and wind acting on the probe in every step. The default is
+/// the puzzle's physics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProbePhysics {
+    /// subtracted from the y velocity
+    pub gravity: i32,
+    /// how far each velocity component moves toward zero
+    pub drag: (i32, i32),
+    /// moves the probe on top of its velocity
+    pub wind: (i32, i32),
 }
 
-fn x_at(x_vel: i32, n: i32) -> i32 {
-    if x_vel > n {
-        (n + 1) * x_vel - gauss(n)
+impl Default for ProbePhysics {
+    fn default() -> Self {
+        ProbePhysics {
+            gravity: 1,
+            drag: (1, 0),
+            wind: (0, 0),
+        }
+    }
+}
+
+impl ProbePhysics {
+    /// Position after `steps` steps, in closed form when there is one.
+    pub fn position(&self, velocity: (i32, i32), steps: usize) -> (i32, i32) {
+        if self.has_closed_form() {
+            self.closed_form(velocity, steps as i32)
+        } else {
+            self.simulate(velocity).nth(steps).unwrap()
+        }
+    }
+
+    /// Positions after every step, starting with the launcher at step 0.
+    pub fn positions(&self, velocity: (i32, i32)) -> impl Iterator<Item = (i32, i32)> {
+        let physics = *self;
+        if self.has_closed_form() {
+            Either::Left((0..).map(move |steps| physics.closed_form(velocity, steps)))
+        } else {
+            Either::Right(self.simulate(velocity))
+        }
+    }
+
+    /// Like `positions`, but going through the steps one by one.
+    pub fn simulate(&self, velocity: (i32, i32)) -> impl Iterator<Item = (i32, i32)> {
+        let physics = *self;
+        iter::successors(Some(((0, 0), velocity)), move |((x, y), (x_vel, y_vel))| {
+            let position = (x + x_vel + physics.wind.0, y + y_vel + physics.wind.1);
+            let velocity = (
+                toward_zero(*x_vel, physics.drag.0),
+                toward_zero(*y_vel, physics.drag.1) - physics.gravity,
+            );
+            Some((position, velocity))
+        })
+        .map(|(position, _)| position)
+    }
+
+    /// Drag on the y axis would have to be applied before gravity in every
+    /// step, which the closed forms do not cover.
+    fn has_closed_form(&self) -> bool {
+        self.drag.0 >= 0 && self.drag.1 == 0
+    }
+
+    fn closed_form(&self, (x_vel, y_vel): (i32, i32), steps: i32) -> (i32, i32) {
+        if steps == 0 {
+            return (0, 0);
+        }
+        (
+            x_at(x_vel, self.drag.0, steps - 1) + steps * self.wind.0,
+            y_at(y_vel, self.gravity, steps - 1) + steps * self.wind.1,
+        )
+    }
+
+    /// Whether the y velocity can never grow, so a shot that is not rising
+    /// anymore stays that way.
+    fn falls_monotonically(&self) -> bool {
+        0 <= self.drag.1 && self.drag.1 <= self.gravity
+    }
+}
+
+fn toward_zero(vel: i32, drag: i32) -> i32 {
+    match vel.signum() {
+        1 => (vel - drag).max(0),
+        -1 => (vel + drag).min(0),
+        _ => 0,
+    }
+}
+
+/// Positions after every step from step 1 on, until the shot is below `y1`
+/// and not rising anymore.
+fn flight(
+    physics: &ProbePhysics,
+    y1: i32,
+    velocity: (i32, i32),
+) -> impl Iterator<Item = (i32, i32)> {
+    let lost = physics.falls_monotonically();
+    physics
+        .positions(velocity)
+        .skip(1)
+        .tuple_windows()
+        .take_while(move |((_, y), (_, next_y))| !(lost && *y < y1 && next_y <= y))
+        .map(|(position, _)| position)
+        .take(MAX_STEPS)
+}
+
+#[derive(Debug, Clone)]
+pub struct Trajectory {
+    target: TargetRectangle,
+    /// position after every step, starting with the launcher at step 0
+    pub positions: Vec<(i32, i32)>,
+    /// first of the highest positions
+    pub apex: (i32, i32),
+    /// first and last step inside the target, if it is hit at all
+    pub hits: Option<(usize, usize)>,
+}
+
+pub fn trace(input: &TargetRectangle, velocity: (i32, i32)) -> Trajectory {
+    trace_with(input, velocity, &ProbePhysics::default())
+}
+
+pub fn trace_with(
+    input: &TargetRectangle,
+    velocity: (i32, i32),
+    physics: &ProbePhysics,
+) -> Trajectory {
+    let target = input.normalized();
+    let positions = iter::once((0, 0))
+        .chain(flight(physics, target.y1, velocity))
+        .collect_vec();
+    let apex = *positions.iter().min_by_key(|(_, y)| Reverse(*y)).unwrap();
+    let inside = positions
+        .iter()
+        .positions(|position| target.contains(*position))
+        .collect_vec();
+    let hits = inside.first().copied().zip(inside.last().copied());
+    Trajectory {
+        target,
+        positions,
+        apex,
+        hits,
+    }
+}
+
+impl Trajectory {
+    fn bounds(&self) -> (i32, i32, i32, i32) {
+        let t = &self.target;
+        let xs = self.positions.iter().map(|(x, _)| *x).chain([t.x1, t.x2]);
+        let ys = self.positions.iter().map(|(_, y)| *y).chain([t.y1, t.y2]);
+        let (min_x, max_x) = xs.minmax().into_option().unwrap();
+        let (min_y, max_y) = ys.minmax().into_option().unwrap();
+        (min_x, max_x, min_y, max_y)
+    }
+
+    /// Draws the launcher as `S`, the probe as `#` and the target as `T`,
+    /// like the puzzle does.
+    pub fn ascii(&self) -> String {
+        let (min_x, max_x, min_y, max_y) = self.bounds();
+        let positions: FnvHashSet<(i32, i32)> = self.positions.iter().copied().collect();
+        (min_y..=max_y)
+            .rev()
+            .map(|y| {
+                (min_x..=max_x)
+                    .map(|x| {
+                        if (x, y) == (0, 0) {
+                            'S'
+                        } else if positions.contains(&(x, y)) {
+                            '#'
+                        } else if self.target.contains((x, y)) {
+                            'T'
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect::<String>()
+            })
+            .join("\n")
+    }
+
+    pub fn svg(&self) -> String {
+        // svg y grows downwards, so every y is negated
+        let (min_x, max_x, min_y, max_y) = self.bounds();
+        let t = &self.target;
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\">\n",
+            min_x - 1,
+            -max_y - 1,
+            max_x - min_x + 2,
+            max_y - min_y + 2
+        );
+        svg += &format!(
+            "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"lightgreen\"/>\n",
+            t.x1 as f32 - 0.5,
+            -t.y2 as f32 - 0.5,
+            t.x2 - t.x1 + 1,
+            t.y2 - t.y1 + 1
+        );
+        let points = self
+            .positions
+            .iter()
+            .map(|(x, y)| format!("{x},{}", -y))
+            .join(" ");
+        svg += &format!(
+            "  <polyline points=\"{points}\" fill=\"none\" stroke=\"black\" stroke-width=\"0.1\"/>\n"
+        );
+        for (x, y) in &self.positions {
+            svg += &format!("  <circle cx=\"{x}\" cy=\"{}\" r=\"0.3\"/>\n", -y);
+        }
+        svg + "</svg>\n"
+    }
+}
+
+/// x after `n + 1` steps, with the velocity getting `drag` closer to zero
+/// every step.
+fn x_at(x_vel: i32, drag: i32, n: i32) -> i32 {
+    if x_vel < 0 {
+        -x_at(-x_vel, drag, n)
+    } else if drag == 0 {
+        (n + 1) * x_vel
     } else {
-        gauss(x_vel)
+        // steps in which the probe still moves
+        let moving = ((x_vel + drag - 1) / drag).min(n + 1);
+        moving * x_vel - drag * gauss(moving - 1)
     }
 }
 
-fn y_at(y_vel: i32, n: i32) -> i32 {
-    (n + 1) * y_vel - gauss(n)
+fn y_at(y_vel: i32, gravity: i32, n: i32) -> i32 {
+    (n + 1) * y_vel - gravity * gauss(n)
 }
 
 fn gauss(i: i32) -> i32 {
@@ -86,3 +347,160 @@ pub fn test() {
     assert_eq!(45, part_1(&generator(&input)));
     // assert_eq!(112, part_2(&generator(&input)));
 }
+
+#[test]
+pub fn test_solve() {
+    let hits = solve(&generator("target area: x=20..30, y=-10..-5")).unwrap();
+    assert_eq!(Some(&vec![7]), hits.get(&(7, 2)));
+    assert_eq!(Some(&vec![5]), hits.get(&(6, 0)));
+    assert_eq!(None, hits.get(&(17, -4)));
+
+    let mirrored = solve(&generator("target area: x=-30..-20, y=-10..-5")).unwrap();
+    assert_eq!(112, mirrored.len());
+    assert!(hits.keys().all(|(x, y)| mirrored.contains_key(&(-x, *y))));
+    assert_eq!(45, part_1(&generator("target area: x=-30..-20, y=-10..-5")));
+
+    assert!(solve(&generator("target area: x=-2..3, y=-4..4")).is_err());
+    assert!(solve(&generator("target area: x=20..30, y=-10..10")).is_err());
+}
+
+#[test]
+pub fn test_solve_brute_force() {
+    for target in [
+        "target area: x=20..30, y=5..10",
+        "target area: x=-4..6, y=-10..-5",
+        "target area: x=-3..-1, y=3..12",
+        "target area: x=0..0, y=-7..-7",
+        "target area: x=11..12, y=-2..3",
+        "target area: x=-20..-16, y=-1..0",
+    ] {
+        let target = generator(target);
+        let hits = solve(&target).unwrap();
+        let mut expected = BTreeMap::new();
+        for x_vel in -60i32..=60 {
+            for y_vel in -60..=60 {
+                let (mut x, mut y, mut dx, mut dy) = (0, 0, x_vel, y_vel);
+                let mut steps = Vec::new();
+                for step in 1..200 {
+                    x += dx;
+                    y += dy;
+                    dx -= dx.signum();
+                    dy -= 1;
+                    if (target.x1..=target.x2).contains(&x) && (target.y1..=target.y2).contains(&y)
+                    {
+                        steps.push(step);
+                    }
+                }
+                if !steps.is_empty() {
+                    expected.insert((x_vel, y_vel), steps);
+                }
+            }
+        }
+        assert_eq!(expected, hits, "{target}");
+    }
+}
+
+#[test]
+pub fn test_trace() {
+    let target = generator("target area: x=20..30, y=-10..-5");
+    let trajectory = trace(&target, (7, 2));
+    assert_eq!((13, 3), trajectory.apex);
+    assert_eq!(Some((7, 7)), trajectory.hits);
+    assert_eq!(8, trajectory.positions.len());
+    let expected = [
+        ".............#....#............",
+        ".......#..............#........",
+        "...............................",
+        "S........................#.....",
+        "...............................",
+        "...............................",
+        "...........................#...",
+        "...............................",
+        "....................TTTTTTTTTTT",
+        "....................TTTTTTTTTTT",
+        "....................TTTTTTTT#TT",
+        "....................TTTTTTTTTTT",
+        "....................TTTTTTTTTTT",
+        "....................TTTTTTTTTTT",
+    ];
+    assert_eq!(expected.join("\n"), trajectory.ascii());
+
+    let trajectory = trace(&target, (6, 0));
+    assert_eq!((0, 0), trajectory.apex);
+    assert_eq!(Some((5, 5)), trajectory.hits);
+    assert_eq!(None, trace(&target, (17, -4)).hits);
+
+    let svg = trace(&target, (6, 3)).svg();
+    assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"-1 -7 32 18\">"));
+    assert!(svg.contains("<rect x=\"19.5\" y=\"4.5\" width=\"11\" height=\"6\""));
+    assert!(svg.contains("points=\"0,0 6,-3 11,-5 15,-6 18,-6 20,-5 21,-3 21,0 21,4 21,9\""));
+}
+
+#[test]
+pub fn test_physics() {
+    let variants = [
+        ProbePhysics::default(),
+        ProbePhysics {
+            gravity: 2,
+            drag: (3, 0),
+            wind: (0, 0),
+        },
+        ProbePhysics {
+            gravity: 1,
+            drag: (0, 0),
+            wind: (-2, 1),
+        },
+        ProbePhysics {
+            gravity: 3,
+            drag: (1, 2),
+            wind: (1, -1),
+        },
+    ];
+    for physics in variants {
+        for x_vel in -12..=12 {
+            for y_vel in -12..=12 {
+                let simulated = physics.simulate((x_vel, y_vel)).take(40).collect_vec();
+                let positions = physics.positions((x_vel, y_vel)).take(40).collect_vec();
+                assert_eq!(simulated, positions, "{physics:?} {x_vel},{y_vel}");
+                assert_eq!(simulated[17], physics.position((x_vel, y_vel), 17));
+            }
+        }
+    }
+
+    // the puzzle's closed forms
+    let physics = ProbePhysics::default();
+    for x_vel in 0..=12 {
+        for n in 0..20 {
+            let gauss_x = if x_vel > n {
+                (n + 1) * x_vel - gauss(n)
+            } else {
+                gauss(x_vel)
+            };
+            let y = (n + 1) * x_vel - gauss(n);
+            assert_eq!(
+                (gauss_x, y),
+                physics.position((x_vel, x_vel), n as usize + 1)
+            );
+        }
+    }
+
+    let target = generator("target area: x=20..30, y=-10..-5");
+    assert_eq!(
+        trace(&target, (7, 2)).positions,
+        trace_with(&target, (7, 2), &physics).positions
+    );
+    let windy = ProbePhysics {
+        wind: (1, 0),
+        ..physics
+    };
+    let trajectory = trace_with(&target, (6, 2), &windy);
+    assert_eq!(Some((7, 7)), trajectory.hits);
+
+    // without gravity nothing comes down, the trace stops anyway
+    let floating = ProbePhysics {
+        gravity: 0,
+        ..physics
+    };
+    let trajectory = trace_with(&target, (1, 1), &floating);
+    assert_eq!(MAX_STEPS + 1, trajectory.positions.len());
+}
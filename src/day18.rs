@@ -1,160 +1,318 @@
 use std::{
+    fmt::{self, Display, Formatter},
     io::{Error, ErrorKind},
+    iter::Sum,
+    ops::{Add, AddAssign},
     str::FromStr,
 };
 
 use itertools::Itertools;
-use parse_display::Display;
 use rayon::iter::IntoParallelRefIterator;
 use rayon::iter::ParallelIterator;
 
-#[derive(Debug, Clone, Display)]
-#[display("[{x},{y}]")]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SnailfishNumber {
-    x: Box<SnailfishValue>,
-    y: Box<SnailfishValue>,
+    /// regular numbers from left to right, with the number of pairs they are nested in
+    values: Vec<(u32, u8)>,
 }
 
-#[derive(Debug, Clone, Display)]
-pub enum SnailfishValue {
-    #[display("{0}")]
-    Pair(SnailfishNumber),
-    #[display("{0}")]
-    Number(u32),
+/// One step of reducing a snailfish number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReduceAction {
+    /// The pair at `path` exploded. Its numbers were added to the next regular number to the
+    /// left and right; `None` if there was no number on that side to take it.
+    Explode {
+        path: String,
+        left: Option<u32>,
+        right: Option<u32>,
+    },
+    /// The regular number `value` at `path` was split into a pair.
+    Split { path: String, value: u32 },
+}
+
+/// A reduce action and the number it left behind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReduceStep {
+    pub action: ReduceAction,
+    pub state: SnailfishNumber,
+}
+
+/// The rules snailfish numbers are reduced and measured by, `Default` gives the puzzle's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnailfishRules {
+    /// pairs nested inside more than this many pairs explode
+    explode_depth: u8,
+    /// regular numbers of at least this value split
+    split_threshold: u32,
+    /// factors of the left and right element in the magnitude of a pair
+    magnitude_weights: (u64, u64),
+}
+
+impl SnailfishRules {
+    /// Pairs nested inside more than `explode_depth` pairs explode, regular numbers of at least
+    /// `split_threshold` split. The depth has to leave the outermost pair in place and room for
+    /// a split below it, the threshold has to be 2 or more for splits to shrink numbers.
+    pub fn new(
+        explode_depth: u8,
+        split_threshold: u32,
+        magnitude_weights: (u64, u64),
+    ) -> Result<SnailfishRules, Error> {
+        if explode_depth == 0 || explode_depth == u8::MAX {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Explode depth {explode_depth} is not between 1 and {}",
+                    u8::MAX - 1
+                ),
+            ));
+        }
+        if split_threshold < 2 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Split threshold {split_threshold} never stops splitting"),
+            ));
+        }
+        Ok(SnailfishRules {
+            explode_depth,
+            split_threshold,
+            magnitude_weights,
+        })
+    }
+}
+
+impl Default for SnailfishRules {
+    fn default() -> Self {
+        SnailfishRules {
+            explode_depth: 4,
+            split_threshold: 10,
+            magnitude_weights: (3, 2),
+        }
+    }
+}
+
+/// What a reduce step did, by index into the flat values.
+enum Step {
+    Explode(usize, Option<u32>, Option<u32>),
+    Split(usize, u32),
 }
 
 impl SnailfishNumber {
-    fn add(&mut self, second: &SnailfishNumber) -> &SnailfishNumber {
-        use SnailfishValue::*;
-        self.x = Box::new(Pair(self.clone()));
-        self.y = Box::new(Pair(second.clone()));
-        // println!("after addition:\t {}", self);
-        self.reduce();
-        self
+    pub fn reduce(&mut self) {
+        self.reduce_with(&SnailfishRules::default());
     }
 
-    fn reduce(&mut self) {
-        let mut work_done = true;
-        while work_done {
-            work_done = self.try_explode(0).2;
+    pub fn reduce_with(&mut self, rules: &SnailfishRules) {
+        while self.step(rules).is_some() {}
+    }
 
-            if !work_done {
-                work_done = self.try_split();
-                // println!("after split:\t {}", self);
-                // } else {
-                // println!("after explode:\t {}", self);
-            }
-        }
+    /// Reduces the number like `reduce`, returning every action taken and the state after it.
+    /// Paths lead from the outermost pair, `L` for the left and `R` for the right element.
+    pub fn reduce_traced(&mut self) -> Vec<ReduceStep> {
+        self.reduce_traced_with(&SnailfishRules::default())
     }
 
-    fn try_explode(&mut self, depth: u8) -> (Option<u32>, Option<u32>, bool) {
-        use SnailfishValue::*;
-        if depth == 4 {
-            // boom!
-            let x_val = match *self.x {
-                Number(val) => val,
-                _ => unreachable!(),
-            };
-            let y_val = match *self.y {
-                Number(val) => val,
-                _ => unreachable!(),
+    pub fn reduce_traced_with(&mut self, rules: &SnailfishRules) -> Vec<ReduceStep> {
+        let mut trace = Vec::new();
+        while let Some(step) = self.step(rules) {
+            let action = match step {
+                // the exploded pair is now the 0 at `idx`
+                Step::Explode(idx, left, right) => ReduceAction::Explode {
+                    path: self.path_of(idx),
+                    left,
+                    right,
+                },
+                // the split number is now the left element of the pair at `idx`
+                Step::Split(idx, value) => {
+                    let mut path = self.path_of(idx);
+                    path.pop();
+                    ReduceAction::Split { path, value }
+                }
             };
-            return (Some(x_val), Some(y_val), true);
+            trace.push(ReduceStep {
+                action,
+                state: self.clone(),
+            });
         }
+        trace
+    }
 
-        if let Pair(val) = &mut *self.x {
-            let mut explode_res = val.try_explode(depth + 1);
-            if let (Some(_), Some(_), _) = explode_res {
-                self.x = Box::new(Number(0));
-            }
-            if let (_, Some(val), _) = explode_res {
-                self.y.add_left(val);
-                explode_res = (explode_res.0, None, explode_res.2)
-            }
+    /// Adds `second` and reduces the sum by `rules`.
+    pub fn add_with(
+        mut self,
+        second: &SnailfishNumber,
+        rules: &SnailfishRules,
+    ) -> Result<Self, Error> {
+        self.nest(second)?;
+        self.reduce_with(rules);
+        Ok(self)
+    }
 
-            if let (_, _, true) = explode_res {
-                return explode_res;
-            }
+    /// Makes a pair of this and `second` without reducing it.
+    fn nest(&mut self, second: &SnailfishNumber) -> Result<(), Error> {
+        if self
+            .values
+            .iter()
+            .chain(&second.values)
+            .any(|&(_, depth)| depth == u8::MAX)
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "The sum of {self} and {second} nests deeper than {} pairs",
+                    u8::MAX
+                ),
+            ));
+        }
+        self.values.extend_from_slice(&second.values);
+        for (_, depth) in &mut self.values {
+            *depth += 1;
         }
+        Ok(())
+    }
 
-        if let Pair(val) = &mut *self.y {
-            let mut explode_res = val.try_explode(depth + 1);
-            if let (Some(_), Some(_), _) = explode_res {
-                self.y = Box::new(Number(0));
-            }
-            if let (Some(val), _, _) = explode_res {
-                self.x.add_right(val);
-                explode_res = (None, explode_res.1, explode_res.2)
-            }
+    fn step(&mut self, rules: &SnailfishRules) -> Option<Step> {
+        self.try_explode(rules.explode_depth)
+            .or_else(|| self.try_split(rules.split_threshold))
+    }
 
-            explode_res
-        } else {
-            (None, None, false)
+    fn try_explode(&mut self, max_depth: u8) -> Option<Step> {
+        // the leftmost pair of two regular numbers nested in too many pairs, pairs further left
+        // that hold another pair have to wait for it to explode first
+        let idx = self
+            .values
+            .windows(2)
+            .position(|pair| pair[0].1 > max_depth && pair[1].1 == pair[0].1)?;
+        let (x_val, depth) = self.values[idx];
+        let (y_val, _) = self.values[idx + 1];
+        let mut left = None;
+        if idx > 0 {
+            self.values[idx - 1].0 += x_val;
+            left = Some(x_val);
         }
+        let mut right = None;
+        if let Some((val, _)) = self.values.get_mut(idx + 2) {
+            *val += y_val;
+            right = Some(y_val);
+        }
+        self.values[idx] = (0, depth - 1);
+        self.values.remove(idx + 1);
+        Some(Step::Explode(idx, left, right))
     }
 
-    fn try_split(&mut self) -> bool {
-        use SnailfishValue::*;
-        let split = match &mut *self.x {
-            Number(val) => {
-                if *val >= 10 {
-                    let x = Box::new(Number(*val / 2));
-                    let y = Box::new(Number((*val + 1) / 2));
-                    *self.x = Pair(SnailfishNumber { x, y });
-                    true
-                } else {
-                    false
-                }
-            }
-            Pair(snail) => snail.try_split(),
-        };
-        if !split {
-            match &mut *self.y {
-                Number(val) => {
-                    if *val >= 10 {
-                        let x = Box::new(Number(*val / 2));
-                        let y = Box::new(Number((*val + 1) / 2));
-                        *self.y = Pair(SnailfishNumber { x, y });
-                        true
-                    } else {
-                        false
-                    }
-                }
-                Pair(snail) => snail.try_split(),
+    fn try_split(&mut self, threshold: u32) -> Option<Step> {
+        let idx = self.values.iter().position(|(val, _)| *val >= threshold)?;
+        let (val, depth) = self.values[idx];
+        self.values[idx] = (val / 2, depth + 1);
+        self.values.insert(idx + 1, (val.div_ceil(2), depth + 1));
+        Some(Step::Split(idx, val))
+    }
+
+    /// Path to the regular number at `idx`.
+    fn path_of(&self, idx: usize) -> String {
+        let mut path = String::new();
+        self.find_path(&mut 0, 0, idx, &mut path);
+        path
+    }
+
+    /// Walks the element starting at `pos` that is nested in `depth` pairs, leaving the path to
+    /// the number at `idx` in `path` if it is part of it.
+    fn find_path(&self, pos: &mut usize, depth: u8, idx: usize, path: &mut String) -> bool {
+        if self.values[*pos].1 == depth {
+            *pos += 1;
+            return *pos - 1 == idx;
+        }
+        for side in ['L', 'R'] {
+            path.push(side);
+            if self.find_path(pos, depth + 1, idx, path) {
+                return true;
             }
-        } else {
-            true
+            path.pop();
         }
+        false
     }
 
-    fn magnitude(&self) -> u64 {
-        3 * self.x.magnitude() + 2 * self.y.magnitude()
+    pub fn magnitude(&self) -> u64 {
+        self.magnitude_with(&SnailfishRules::default())
     }
-}
 
-impl SnailfishValue {
-    fn add_left(&mut self, by: u32) {
-        use SnailfishValue::*;
-        match self {
-            Pair(val) => val.x.add_left(by),
-            Number(val) => *val += by,
-        }
+    pub fn magnitude_with(&self, rules: &SnailfishRules) -> u64 {
+        self.magnitude_at(&mut 0, 0, rules.magnitude_weights)
     }
 
-    fn add_right(&mut self, by: u32) {
-        use SnailfishValue::*;
-        match self {
-            Pair(val) => val.y.add_right(by),
-            Number(val) => *val += by,
+    /// Magnitude of the element starting at `pos` that is nested in `depth` pairs.
+    fn magnitude_at(&self, pos: &mut usize, depth: u8, weights: (u64, u64)) -> u64 {
+        let (val, val_depth) = self.values[*pos];
+        if val_depth == depth {
+            *pos += 1;
+            return val as u64;
         }
+        let left = self.magnitude_at(pos, depth + 1, weights);
+        weights.0 * left + weights.1 * self.magnitude_at(pos, depth + 1, weights)
     }
 
-    fn magnitude(&self) -> u64 {
-        use SnailfishValue::*;
-        match self {
-            Pair(val) => val.magnitude(),
-            Number(val) => *val as u64,
+    fn fmt_at(&self, f: &mut Formatter<'_>, pos: &mut usize, depth: u8) -> fmt::Result {
+        let (val, val_depth) = self.values[*pos];
+        if val_depth == depth {
+            *pos += 1;
+            return write!(f, "{val}");
         }
+        write!(f, "[")?;
+        self.fmt_at(f, pos, depth + 1)?;
+        write!(f, ",")?;
+        self.fmt_at(f, pos, depth + 1)?;
+        write!(f, "]")
+    }
+}
+
+/// Panics if the sum nests deeper than 255 pairs, `add_with` returns an error instead.
+impl AddAssign<&SnailfishNumber> for SnailfishNumber {
+    fn add_assign(&mut self, second: &SnailfishNumber) {
+        self.nest(second).unwrap();
+        self.reduce();
+    }
+}
+
+impl AddAssign for SnailfishNumber {
+    fn add_assign(&mut self, second: SnailfishNumber) {
+        *self += &second;
+    }
+}
+
+impl Add<&SnailfishNumber> for SnailfishNumber {
+    type Output = SnailfishNumber;
+
+    fn add(mut self, second: &SnailfishNumber) -> SnailfishNumber {
+        self += second;
+        self
+    }
+}
+
+impl Add for SnailfishNumber {
+    type Output = SnailfishNumber;
+
+    fn add(self, second: SnailfishNumber) -> SnailfishNumber {
+        self + &second
+    }
+}
+
+/// Snailfish numbers have no zero, so summing up nothing panics.
+impl Sum for SnailfishNumber {
+    fn sum<I: Iterator<Item = SnailfishNumber>>(iter: I) -> Self {
+        iter.reduce(|accum, snail| accum + snail)
+            .expect("no snailfish numbers to sum up")
+    }
+}
+
+impl<'a> Sum<&'a SnailfishNumber> for SnailfishNumber {
+    fn sum<I: Iterator<Item = &'a SnailfishNumber>>(mut iter: I) -> Self {
+        let first = iter.next().expect("no snailfish numbers to sum up").clone();
+        iter.fold(first, |accum, snail| accum + snail)
+    }
+}
+
+impl Display for SnailfishNumber {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.fmt_at(f, &mut 0, 0)
     }
 }
 
@@ -166,66 +324,91 @@ impl FromStr for SnailfishNumber {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        // find the middle comma to split
-        let mut open_bracket_count = 0;
-        let mut found_idx = None;
-        'search: for (index, char) in s.char_indices() {
-            match char {
-                '[' => open_bracket_count += 1,
-                ']' => open_bracket_count -= 1,
-                ',' => {
-                    if open_bracket_count == 1 {
-                        found_idx = Some(index);
-                        break 'search;
-                    }
-                }
-                _ => (),
-            }
+        let mut parser = Parser {
+            s,
+            pos: 0,
+            values: Vec::new(),
+        };
+        parser.pair(1)?;
+        if parser.pos < s.len() {
+            return Err(parser.error("end of input"));
         }
-        let found_idx = found_idx.ok_or(Error::new(
-            ErrorKind::InvalidData,
-            format!("Could not parse {s}"),
-        ))?;
-
-        let x = Box::new(s[1..found_idx].parse()?);
-        let y = Box::new(s[found_idx + 1..s.len() - 1].parse()?);
-
-        Ok(SnailfishNumber { x, y })
+        Ok(SnailfishNumber {
+            values: parser.values,
+        })
     }
 }
 
-impl FromStr for SnailfishValue {
-    type Err = Error;
+struct Parser<'a> {
+    s: &'a str,
+    pos: usize,
+    values: Vec<(u32, u8)>,
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.chars().next().ok_or(Error::new(
+impl Parser<'_> {
+    fn error(&self, expected: &str) -> Error {
+        let found = match self.s[self.pos..].chars().next() {
+            Some(char) => format!("'{char}'"),
+            None => "end of input".to_string(),
+        };
+        Error::new(
             ErrorKind::InvalidData,
-            format!("Could not parse {s}"),
-        ))? == '['
-        {
-            Ok(Self::Pair(s.parse()?))
-        } else {
-            Ok(Self::Number(s.parse().unwrap()))
+            format!(
+                "Expected {expected} at {}, found {found} in {}",
+                self.pos, self.s
+            ),
+        )
+    }
+
+    fn expect(&mut self, char: u8) -> Result<(), Error> {
+        if self.s.as_bytes().get(self.pos) != Some(&char) {
+            return Err(self.error(&format!("'{}'", char as char)));
         }
+        self.pos += 1;
+        Ok(())
     }
-}
 
-pub fn part_1(snails: &Vec<SnailfishNumber>) -> u64 {
-    let mut accum = snails[0].clone();
-    for snail in snails.iter().skip(1) {
-        accum.add(snail);
+    /// A pair whose regular numbers are nested in `depth` pairs.
+    fn pair(&mut self, depth: u8) -> Result<(), Error> {
+        self.expect(b'[')?;
+        self.element(depth)?;
+        self.expect(b',')?;
+        self.element(depth)?;
+        self.expect(b']')
     }
 
-    accum.magnitude()
+    fn element(&mut self, depth: u8) -> Result<(), Error> {
+        let bytes = self.s.as_bytes();
+        match bytes.get(self.pos) {
+            Some(b'[') if depth < u8::MAX => self.pair(depth + 1),
+            Some(b'0'..=b'9') => {
+                let len = bytes[self.pos..]
+                    .iter()
+                    .take_while(|byte| byte.is_ascii_digit())
+                    .count();
+                let val = self.s[self.pos..self.pos + len]
+                    .parse()
+                    .map_err(|_| self.error("a number that fits into 32 bits"))?;
+                self.values.push((val, depth));
+                self.pos += len;
+                Ok(())
+            }
+            _ => Err(self.error("a number or '['")),
+        }
+    }
 }
 
-pub fn part_2(snails: &Vec<SnailfishNumber>) -> u64 {
+pub fn part_1(snails: &[SnailfishNumber]) -> u64 {
+    snails.iter().sum::<SnailfishNumber>().magnitude()
+}
+
+pub fn part_2(snails: &[SnailfishNumber]) -> u64 {
     snails
         .iter()
         .tuple_combinations()
         .collect::<Vec<(&SnailfishNumber, &SnailfishNumber)>>()
         .par_iter()
-        .map(|(a, b)| (*a).clone().add(b).magnitude())
+        .map(|(a, b)| ((*a).clone() + *b).magnitude())
         .max()
         .unwrap()
 }
@@ -238,21 +421,272 @@ pub fn test() {
     [4,4]\n\
     [5,5]\n\
     [6,6]";
-    let snails = generator(&input); // TODO: Debug
+    let snails = generator(&input);
     let mut accum = snails[0].clone();
+    let mut sums = Vec::new();
     for snail in snails.iter().skip(1) {
-        accum.add(snail);
-        println!("Accum: {}", accum);
+        accum += snail;
+        sums.push(accum.to_string());
     }
-    assert_eq!(1, 1);
+    assert_eq!("[[[[1,1],[2,2]],[3,3]],[4,4]]", sums[2]);
+    assert_eq!("[[[[3,0],[5,3]],[4,4]],[5,5]]", sums[3]);
+    assert_eq!("[[[[5,0],[7,4]],[5,5]],[6,6]]", sums[4]);
 }
 
 #[test]
 fn test_explo() {
-    let input = "[[[[4,3],4],4],[7,[[8,4],9]]]\n[1,1]";
-    let vals = generator(&input);
-    let mut first = vals[0].clone();
-    let second = &vals[1];
+    let explode = |s: &str| {
+        let mut snail: SnailfishNumber = s.parse().unwrap();
+        let trace = snail.reduce_traced();
+        assert_eq!(1, trace.len());
+        (trace[0].action.clone(), snail.to_string())
+    };
+    let action = |path: &str, left, right| ReduceAction::Explode {
+        path: path.to_string(),
+        left,
+        right,
+    };
+    assert_eq!(
+        (
+            action("LLLL", None, Some(8)),
+            "[[[[0,9],2],3],4]".to_string()
+        ),
+        explode("[[[[[9,8],1],2],3],4]")
+    );
+    assert_eq!(
+        (
+            action("RRRR", Some(3), None),
+            "[7,[6,[5,[7,0]]]]".to_string()
+        ),
+        explode("[7,[6,[5,[4,[3,2]]]]]")
+    );
+    assert_eq!(
+        (
+            action("LRRR", Some(3), Some(2)),
+            "[[6,[5,[7,0]]],3]".to_string()
+        ),
+        explode("[[6,[5,[4,[3,2]]]],1]")
+    );
+
+    let snails = generator("[[[[4,3],4],4],[7,[[8,4],9]]]\n[1,1]");
+    let mut sum = snails[0].clone();
+    sum.nest(&snails[1]).unwrap();
+    assert_eq!("[[[[[4,3],4],4],[7,[[8,4],9]]],[1,1]]", sum.to_string());
+
+    let trace = sum.reduce_traced();
+    let states: Vec<String> = trace.iter().map(|step| step.state.to_string()).collect();
+    assert_eq!(
+        vec![
+            "[[[[0,7],4],[7,[[8,4],9]]],[1,1]]",
+            "[[[[0,7],4],[15,[0,13]]],[1,1]]",
+            "[[[[0,7],4],[[7,8],[0,13]]],[1,1]]",
+            "[[[[0,7],4],[[7,8],[0,[6,7]]]],[1,1]]",
+            "[[[[0,7],4],[[7,8],[6,0]]],[8,1]]",
+        ],
+        states
+    );
+    let split = |path: &str, value| ReduceAction::Split {
+        path: path.to_string(),
+        value,
+    };
+    assert_eq!(
+        vec![
+            action("LLLL", None, Some(3)),
+            action("LRRL", Some(8), Some(4)),
+            split("LRL", 15),
+            split("LRRR", 13),
+            action("LRRR", Some(6), Some(7)),
+        ],
+        trace
+            .into_iter()
+            .map(|step| step.action)
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(snails[0].clone() + &snails[1], sum);
+
+    // the leftmost pair that is too deep holds another pair, which has to go first
+    let mut snail: SnailfishNumber = "[[[[[1,[2,3]],4],5],6],7]".parse().unwrap();
+    let trace = snail.reduce_traced();
+    assert_eq!(
+        vec![
+            (
+                action("LLLLR", Some(2), Some(3)),
+                "[[[[[3,0],7],5],6],7]".to_string()
+            ),
+            (
+                action("LLLL", None, Some(0)),
+                "[[[[0,7],5],6],7]".to_string()
+            ),
+        ],
+        trace
+            .into_iter()
+            .map(|step| (step.action, step.state.to_string()))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_flat() {
+    let input = "[[[0,[5,8]],[[1,7],[9,6]]],[[4,[1,2]],[[1,4],2]]]\n\
+    [[[5,[2,8]],4],[5,[[9,9],0]]]\n\
+    [6,[[[6,2],[5,6]],[[7,6],[4,7]]]]\n\
+    [[[6,[0,7]],[0,9]],[4,[9,[9,0]]]]\n\
+    [[[7,[6,4]],[3,[1,3]]],[[[5,5],1],9]]\n\
+    [[6,[[7,3],[3,2]]],[[[3,8],[5,7]],4]]\n\
+    [[[[5,4],[7,7]],8],[[8,3],8]]\n\
+    [[9,3],[[9,9],[6,[4,9]]]]\n\
+    [[2,[[7,7],7]],[[5,8],[[9,3],[0,2]]]]\n\
+    [[[[5,2],5],[8,[3,7]]],[[5,[7,5]],[4,4]]]";
+    let snails = generator(input);
+    assert_eq!("[6,[[[6,2],[5,6]],[[7,6],[4,7]]]]", snails[2].to_string());
+    assert_eq!(4140, part_1(&snails));
+
+    let sum = snails[0].clone() + &snails[1];
+    assert_eq!(
+        "[[[[7,0],[7,8]],[[7,9],[0,6]]],[[[7,0],[6,6]],[[7,7],[0,9]]]]",
+        sum.to_string()
+    );
+    let mag: SnailfishNumber = "[[[[8,7],[7,7]],[[8,6],[7,7]]],[[[0,7],[6,6]],[8,7]]]"
+        .parse()
+        .unwrap();
+    assert_eq!(3488, mag.magnitude());
+    assert_eq!(
+        vec![(13, 1), (2, 1)],
+        "[13,2]".parse::<SnailfishNumber>().unwrap().values
+    );
+    assert!("[1,2".parse::<SnailfishNumber>().is_err());
+}
+
+#[test]
+fn test_operators() {
+    let snails = generator("[[[[4,3],4],4],[7,[[8,4],9]]]\n[1,1]");
+    let expected = "[[[[0,7],4],[[7,8],[6,0]]],[8,1]]";
+    assert_eq!(
+        expected,
+        (snails[0].clone() + snails[1].clone()).to_string()
+    );
+    assert_eq!(expected, snails.iter().sum::<SnailfishNumber>().to_string());
+    assert_eq!(
+        expected,
+        snails.into_iter().sum::<SnailfishNumber>().to_string()
+    );
+
+    let mut accum: SnailfishNumber = "[1,1]".parse().unwrap();
+    accum += "[2,2]".parse::<SnailfishNumber>().unwrap();
+    assert_eq!("[[1,1],[2,2]]", accum.to_string());
+    assert_eq!(3 * (3 + 2) + 2 * (6 + 4), accum.magnitude());
+
+    let mut unreduced = SnailfishNumber {
+        values: vec![(11, 1), (1, 1)],
+    };
+    unreduced.reduce();
+    assert_eq!("[[5,6],1]", unreduced.to_string());
+}
+
+#[test]
+fn test_parse_errors() {
+    let error = |s: &str| s.parse::<SnailfishNumber>().unwrap_err().to_string();
+    assert_eq!(
+        "Expected ']' at 4, found end of input in [1,2",
+        error("[1,2")
+    );
+    assert_eq!(
+        "Expected end of input at 5, found ']' in [1,2]]",
+        error("[1,2]]")
+    );
+    assert_eq!(
+        "Expected a number or '[' at 1, found ',' in [,2]",
+        error("[,2]")
+    );
+    assert_eq!(
+        "Expected a number or '[' at 3, found ']' in [1,]",
+        error("[1,]")
+    );
+    assert_eq!("Expected ',' at 2, found ' ' in [1 ,2]", error("[1 ,2]"));
+    assert_eq!(
+        "Expected end of input at 5, found 'x' in [1,2]x",
+        error("[1,2]x")
+    );
+    assert_eq!("Expected '[' at 0, found '7' in 7", error("7"));
+    assert_eq!("Expected '[' at 0, found end of input in ", error(""));
+    assert_eq!(
+        "Expected a number that fits into 32 bits at 1, found '9' in [99999999999,1]",
+        error("[99999999999,1]")
+    );
+    assert_eq!(
+        "Expected ',' at 3, found ']' in [[1][2,3]]",
+        error("[[1][2,3]]")
+    );
+}
+
+#[test]
+fn test_rules() {
+    let snails = generator("[[[[4,3],4],4],[7,[[8,4],9]]]\n[1,1]");
+    let puzzle = SnailfishRules::default();
+    assert_eq!(
+        snails[0].clone() + &snails[1],
+        snails[0].clone().add_with(&snails[1], &puzzle).unwrap()
+    );
+
+    let deeper = SnailfishRules::new(5, 10, (3, 2)).unwrap();
+    let sum = snails[0].clone().add_with(&snails[1], &deeper).unwrap();
+    assert_eq!("[[[[[4,3],4],4],[7,[[8,4],9]]],[1,1]]", sum.to_string());
+
+    let shallower = SnailfishRules::new(1, 10, (3, 2)).unwrap();
+    let snail: SnailfishNumber = "[[1,[2,3]],4]".parse().unwrap();
+    let sum = snail
+        .add_with(&"[1,1]".parse().unwrap(), &shallower)
+        .unwrap();
+    assert_eq!("[8,0]", sum.to_string());
+
+    let small = SnailfishRules::new(4, 5, (3, 2)).unwrap();
+    let mut snail: SnailfishNumber = "[[[[0,7],4],[7,[6,4]]],[1,1]]".parse().unwrap();
+    let actions: Vec<_> = snail
+        .reduce_traced_with(&small)
+        .into_iter()
+        .map(|step| step.action)
+        .collect();
+    assert_eq!(
+        ReduceAction::Split {
+            path: "LLLR".to_string(),
+            value: 7
+        },
+        actions[0]
+    );
+    assert!(snail
+        .values
+        .iter()
+        .all(|&(val, depth)| val < 5 && depth <= 4));
+
+    let mirrored = SnailfishRules::new(4, 10, (2, 3)).unwrap();
+    let pair: SnailfishNumber = "[[1,2],[[3,4],5]]".parse().unwrap();
+    assert_eq!(143, pair.magnitude_with(&puzzle));
+    assert_eq!(
+        2 * (2 + 6) + 3 * (2 * (6 + 12) + 15),
+        pair.magnitude_with(&mirrored)
+    );
 
-    first.add(second);
+    assert_eq!(
+        "Explode depth 0 is not between 1 and 254",
+        SnailfishRules::new(0, 10, (3, 2)).unwrap_err().to_string()
+    );
+    assert!(SnailfishRules::new(u8::MAX, 10, (3, 2)).is_err());
+    assert_eq!(
+        "Split threshold 1 never stops splitting",
+        SnailfishRules::new(4, 1, (3, 2)).unwrap_err().to_string()
+    );
+    let loosest = SnailfishRules::new(u8::MAX - 1, 10, (3, 2)).unwrap();
+    let deepest: SnailfishNumber = format!("{}1,2]{}", "[".repeat(255), ",0]".repeat(254))
+        .parse()
+        .unwrap();
+    assert!(deepest
+        .clone()
+        .add_with(&"[1,1]".parse().unwrap(), &loosest)
+        .is_err());
+    let mut reduced = deepest;
+    reduced.reduce_with(&loosest);
+    assert!(reduced.values.iter().all(|&(_, depth)| depth < u8::MAX));
+    assert!(reduced
+        .add_with(&"[1,1]".parse().unwrap(), &loosest)
+        .is_ok());
 }
@@ -1,155 +1,583 @@
-use fnv::FnvHashSet;
+use std::{
+    fmt::{self, Display, Formatter},
+    fs,
+    io::{Error, ErrorKind},
+    path::{Path, PathBuf},
+};
 
 pub struct Input {
     light_lookup: Vec<bool>,
-    initial_image: FnvHashSet<(i32, i32)>,
+    initial_image: Image,
 }
 
-pub fn generator(input: &str) -> Input {
-    let mut lines = input.lines();
-    let lookup = input
-        .lines()
-        .next()
-        .unwrap()
-        .chars()
-        .map(|c| c == '#')
-        .collect();
-    lines.next();
-    let is_point = |x, y, c| {
-        if c == '#' {
-            Some((x, y))
+/// A window onto an infinite image, every pixel outside of it has the `background` value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    /// one bit per pixel, each row starting at a new word
+    bits: Vec<u64>,
+    background: bool,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, background: bool) -> Image {
+        Image {
+            width,
+            height,
+            bits: vec![0; Image::row_words(width) * height],
+            background,
+        }
+    }
+
+    fn row_words(width: usize) -> usize {
+        width.div_ceil(64)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn background(&self) -> bool {
+        self.background
+    }
+
+    /// The pixel in column `x` of row `y`, which may lie outside of the window.
+    pub fn get(&self, x: isize, y: isize) -> bool {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return self.background;
+        }
+        let (x, y) = (x as usize, y as usize);
+        self.bits[y * Image::row_words(self.width) + x / 64] >> (x % 64) & 1 == 1
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, lit: bool) {
+        let word = &mut self.bits[y * Image::row_words(self.width) + x / 64];
+        if lit {
+            *word |= 1 << (x % 64);
         } else {
-            None
+            *word &= !(1 << (x % 64));
         }
-    };
-    let initial_image = lines
-        .zip(0..)
-        .flat_map(|(line, x_idx)| {
-            line.chars()
-                .zip(1..)
-                .flat_map(move |(char, y_idx)| is_point(x_idx, y_idx, char))
-        })
-        .collect();
+    }
 
-    Input {
-        light_lookup: lookup,
-        initial_image,
+    /// Number of lit pixels, `None` if the lit background makes it infinite.
+    pub fn lit_pixels(&self) -> Option<usize> {
+        if self.background {
+            return None;
+        }
+        Some(
+            self.bits
+                .iter()
+                .map(|word| word.count_ones() as usize)
+                .sum(),
+        )
     }
-}
 
-pub fn part_1(input: &Input) -> usize {
-    let mut curr = input.initial_image.clone();
-
-    let mut dark_mode = false;
-
-    for _ in 0..2 {
-        let x_max = curr.iter().map(|(x, _y)| *x).max().unwrap();
-        let x_min = curr.iter().map(|(x, _y)| *x).min().unwrap();
-        let y_max = curr.iter().map(|(_x, y)| *y).max().unwrap();
-        let y_min = curr.iter().map(|(_x, y)| *y).min().unwrap();
-        let mut next = FnvHashSet::default();
-
-        for x in x_min - 20..x_max + 20 {
-            for y in y_min - 20..y_max + 20 {
-                let x8 = get_value(x - 1, y - 1, &curr, 8, dark_mode);
-                let x7 = get_value( x - 1, y, &curr, 7, dark_mode);
-                let x6 = get_value( x - 1, y + 1, &curr, 6, dark_mode);
-                let x5 = get_value( x, y - 1, &curr, 5, dark_mode);
-                let x4 = get_value( x, y, &curr, 4, dark_mode);
-                let x3 = get_value( x, y + 1, &curr, 3, dark_mode);
-                let x2 = get_value( x + 1, y - 1, &curr, 2, dark_mode);
-                let x1 = get_value( x + 1, y, &curr, 1, dark_mode);
-                let x0 = get_value( x + 1, y + 1, &curr, 0, dark_mode);
-
-                if input.light_lookup[x8 + x7 + x6 + x5 + x4 + x3 + x2 + x1 + x0] == dark_mode {
-                    next.insert((x, y));
+    /// Applies `lookup` once, growing the window by one pixel on every side. Every pixel becomes
+    /// the entry of the 9 bit number read from its 3x3 neighbourhood, row by row from the top
+    /// left, so the background follows entry 0 or 511.
+    pub fn enhance(&self, lookup: &[bool]) -> Image {
+        let background = lookup[if self.background { 0b111_111_111 } else { 0 }];
+        let mut next = Image::new(self.width + 2, self.height + 2, background);
+        for y in 0..next.height {
+            let old_y = y as isize - 1;
+            // the neighbourhood slides to the right one column at a time
+            let mut idx = if self.background { 0b110_110_110 } else { 0 };
+            for x in 0..next.width {
+                let old_x = x as isize;
+                let column = (self.get(old_x, old_y - 1) as usize) << 6
+                    | (self.get(old_x, old_y) as usize) << 3
+                    | self.get(old_x, old_y + 1) as usize;
+                idx = (idx << 1) & 0b110_110_110 | column;
+                if lookup[idx] {
+                    next.set(x, y, true);
                 }
             }
         }
-        
-        //####################..................##.#######.########..#...#..#..###...#...###.#.##.###..###.#.##....###..#.############...#.###...##.###.................#.##################
-        // _print_map(&curr);
-        dark_mode = !dark_mode;
-        curr = next;
+        next
     }
-    _print_map(&curr);
 
-    let x_max = curr.iter().map(|(x, _y)| *x).max().unwrap();
-    let x_min = curr.iter().map(|(x, _y)| *x).min().unwrap();
-    let y_max = curr.iter().map(|(_x, y)| *y).max().unwrap();
-    let y_min = curr.iter().map(|(_x, y)| *y).min().unwrap();
+    /// The smallest window that still shows every pixel that differs from the background.
+    pub fn trimmed(&self) -> Image {
+        let differs = |x: usize, y: usize| self.get(x as isize, y as isize) != self.background;
+        let rows: Vec<usize> = (0..self.height)
+            .filter(|&y| (0..self.width).any(|x| differs(x, y)))
+            .collect();
+        let columns: Vec<usize> = (0..self.width)
+            .filter(|&x| rows.iter().any(|&y| differs(x, y)))
+            .collect();
+        let (top, left) = match (rows.first(), columns.first()) {
+            (Some(&top), Some(&left)) => (top, left),
+            _ => return Image::new(0, 0, self.background),
+        };
+        let width = columns[columns.len() - 1] - left + 1;
+        let height = rows[rows.len() - 1] - top + 1;
+        let mut trimmed = Image::new(width, height, self.background);
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = self.get((left + x) as isize, (top + y) as isize);
+                trimmed.set(x, y, pixel);
+            }
+        }
+        trimmed
+    }
 
-    // curr.iter().filter((x, y) x ).len()
-    curr.len()
+    /// The image with `margin` more background pixels on every side.
+    pub fn padded(&self, margin: usize) -> Image {
+        let mut padded = Image::new(
+            self.width + 2 * margin,
+            self.height + 2 * margin,
+            self.background,
+        );
+        for y in 0..padded.height {
+            for x in 0..padded.width {
+                let pixel = self.get(x as isize - margin as isize, y as isize - margin as isize);
+                padded.set(x, y, pixel);
+            }
+        }
+        padded
+    }
+
+    /// Plain PBM with lit pixels in black, like the `#` of the puzzle.
+    pub fn to_pbm(&self) -> String {
+        let pixels = (0..self.height).map(|y| {
+            (0..self.width)
+                .map(move |x| {
+                    if self.get(x as isize, y as isize) {
+                        "1"
+                    } else {
+                        "0"
+                    }
+                })
+                .collect::<Vec<_>>()
+        });
+        Image::plain_pnm(format!("P1\n{} {}\n", self.width, self.height), pixels, "")
+    }
+
+    /// Plain PGM with lit pixels in black and dark pixels in white.
+    pub fn to_pgm(&self) -> String {
+        let pixels = (0..self.height).map(|y| {
+            (0..self.width)
+                .map(move |x| {
+                    if self.get(x as isize, y as isize) {
+                        "0"
+                    } else {
+                        "255"
+                    }
+                })
+                .collect::<Vec<_>>()
+        });
+        Image::plain_pnm(
+            format!("P2\n{} {}\n255\n", self.width, self.height),
+            pixels,
+            " ",
+        )
+    }
+
+    /// Writes each row of `pixels` after `header`, breaking lines before they get longer than
+    /// the 70 characters the formats allow.
+    fn plain_pnm(
+        mut pnm: String,
+        pixels: impl Iterator<Item = Vec<&'static str>>,
+        separator: &str,
+    ) -> String {
+        for row in pixels {
+            let mut line_len = 0;
+            for pixel in row {
+                if line_len + separator.len() + pixel.len() > 70 {
+                    pnm.push('\n');
+                    line_len = 0;
+                } else if line_len > 0 {
+                    pnm.push_str(separator);
+                    line_len += separator.len();
+                }
+                pnm.push_str(pixel);
+                line_len += pixel.len();
+            }
+            pnm.push('\n');
+        }
+        pnm
+    }
+
+    /// Reads a PBM or PGM image, plain or raw. Black PBM pixels and PGM pixels darker than half
+    /// the maximum value are lit.
+    pub fn from_pnm(data: &[u8]) -> Result<Image, Error> {
+        let mut reader = PnmReader { data, pos: 0 };
+        let magic = reader.data.get(..2).unwrap_or_default();
+        if ![&b"P1"[..], b"P2", b"P4", b"P5"].contains(&magic) {
+            return Err(reader.error("expected P1, P2, P4 or P5"));
+        }
+        reader.pos = 2;
+        let width = reader.number()?;
+        let height = reader.number()?;
+        let max_val = match magic {
+            b"P2" | b"P5" => reader.number()?,
+            _ => 1,
+        };
+        if max_val == 0 || max_val > u16::MAX as usize {
+            return Err(reader.error(&format!("maximum value {max_val} out of range")));
+        }
+        let lit = |val: usize| val * 2 < max_val;
+
+        let mut image = Image::new(width, height, false);
+        match magic {
+            b"P1" => {
+                for (y, x) in (0..height).flat_map(|y| (0..width).map(move |x| (y, x))) {
+                    reader.skip_space();
+                    match reader.byte()? {
+                        b'0' => {}
+                        b'1' => image.set(x, y, true),
+                        _ => return Err(reader.error("expected 0 or 1")),
+                    }
+                }
+            }
+            b"P2" => {
+                for (y, x) in (0..height).flat_map(|y| (0..width).map(move |x| (y, x))) {
+                    image.set(x, y, lit(reader.number()?));
+                }
+            }
+            b"P4" => {
+                reader.pos += 1;
+                for y in 0..height {
+                    let row = reader.bytes(width.div_ceil(8))?;
+                    for x in 0..width {
+                        image.set(x, y, row[x / 8] >> (7 - x % 8) & 1 == 1);
+                    }
+                }
+            }
+            b"P5" => {
+                reader.pos += 1;
+                let depth = if max_val < 256 { 1 } else { 2 };
+                for y in 0..height {
+                    let row = reader.bytes(width * depth)?;
+                    for (x, val) in row.chunks(depth).enumerate() {
+                        let val = val.iter().fold(0, |val, &byte| val << 8 | byte as usize);
+                        image.set(x, y, lit(val));
+                    }
+                }
+            }
+            _ => unreachable!(),
+        }
+        Ok(image)
+    }
+
+    /// Writes the image as PGM if `path` ends in `.pgm`, else as PBM.
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let pnm = match path.extension() {
+            Some(extension) if extension == "pgm" => self.to_pgm(),
+            _ => self.to_pbm(),
+        };
+        fs::write(path, pnm)
+    }
 }
 
+struct PnmReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
 
-pub fn part_2(input: &Input) -> usize {
-    let mut curr = input.initial_image.clone();
-
-    let mut dark_mode = false;
-
-    for _ in 0..50 {
-        let x_max = curr.iter().map(|(x, _y)| *x).max().unwrap();
-        let x_min = curr.iter().map(|(x, _y)| *x).min().unwrap();
-        let y_max = curr.iter().map(|(_x, y)| *y).max().unwrap();
-        let y_min = curr.iter().map(|(_x, y)| *y).min().unwrap();
-        let mut next = FnvHashSet::default();
-
-        for x in x_min - 20..x_max + 20 {
-            for y in y_min - 20..y_max + 20 {
-                let x8 = get_value(x - 1, y - 1, &curr, 8, dark_mode);
-                let x7 = get_value( x - 1, y, &curr, 7, dark_mode);
-                let x6 = get_value( x - 1, y + 1, &curr, 6, dark_mode);
-                let x5 = get_value( x, y - 1, &curr, 5, dark_mode);
-                let x4 = get_value( x, y, &curr, 4, dark_mode);
-                let x3 = get_value( x, y + 1, &curr, 3, dark_mode);
-                let x2 = get_value( x + 1, y - 1, &curr, 2, dark_mode);
-                let x1 = get_value( x + 1, y, &curr, 1, dark_mode);
-                let x0 = get_value( x + 1, y + 1, &curr, 0, dark_mode);
-
-                if input.light_lookup[x8 + x7 + x6 + x5 + x4 + x3 + x2 + x1 + x0] == dark_mode {
-                    next.insert((x, y));
+impl PnmReader<'_> {
+    fn error(&self, reason: &str) -> Error {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("Invalid PNM image at byte {}: {reason}", self.pos),
+        )
+    }
+
+    /// Skips whitespace and comments, which run from `#` to the end of the line.
+    fn skip_space(&mut self) {
+        while let Some(&byte) = self.data.get(self.pos) {
+            if byte == b'#' {
+                while self.data.get(self.pos).is_some_and(|&byte| byte != b'\n') {
+                    self.pos += 1;
                 }
+            } else if byte.is_ascii_whitespace() {
+                self.pos += 1;
+            } else {
+                break;
             }
         }
-        
-        dark_mode = !dark_mode;
-        curr = next;
     }
 
+    fn byte(&mut self) -> Result<u8, Error> {
+        let byte = *self
+            .data
+            .get(self.pos)
+            .ok_or_else(|| self.error("unexpected end of data"))?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&[u8], Error> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| self.error("unexpected end of data"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
 
-    curr.len()
+    fn number(&mut self) -> Result<usize, Error> {
+        self.skip_space();
+        let digits = self.data.get(self.pos..).unwrap_or_default();
+        let len = digits
+            .iter()
+            .take_while(|byte| byte.is_ascii_digit())
+            .count();
+        let number = std::str::from_utf8(&digits[..len])
+            .unwrap()
+            .parse()
+            .map_err(|_| self.error("expected a number"))?;
+        self.pos += len;
+        Ok(number)
+    }
 }
 
-fn get_value(x: i32, y: i32, set: &FnvHashSet<(i32, i32)>, bit: u8, dark_mode: bool) -> usize {
-    if set.contains(&(x, y)) != dark_mode {
-        1 << bit
-    } else {
-        0
+/// The lookup for a Life-like rule such as `B3/S23`: a dark pixel becomes lit with as many lit
+/// neighbours as one of the digits after `B`, a lit pixel stays lit with one after `S`.
+pub fn life_rule(rule: &str) -> Result<Vec<bool>, Error> {
+    let error = |reason: &str| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("Invalid rule {rule}: {reason}"),
+        )
+    };
+    let mut birth = None;
+    let mut survival = None;
+    for part in rule.trim().split('/') {
+        let mut chars = part.chars();
+        let counts = match chars.next().map(|c| c.to_ascii_uppercase()) {
+            Some('B') => &mut birth,
+            Some('S') => &mut survival,
+            _ => return Err(error("expected B or S before the neighbour counts")),
+        };
+        if counts.is_some() {
+            return Err(error("B and S may only appear once"));
+        }
+        let mut neighbours = [false; 9];
+        for char in chars {
+            match char.to_digit(10) {
+                Some(count) if count <= 8 => neighbours[count as usize] = true,
+                _ => {
+                    return Err(error(&format!(
+                        "'{char}' is not a count of 0 to 8 neighbours"
+                    )))
+                }
+            }
+        }
+        *counts = Some(neighbours);
     }
+    let (birth, survival) = match (birth, survival) {
+        (Some(birth), Some(survival)) => (birth, survival),
+        _ => return Err(error("expected both B and S")),
+    };
+
+    Ok((0..512usize)
+        .map(|idx| {
+            let neighbours = (idx & !0b000_010_000).count_ones() as usize;
+            if idx & 0b000_010_000 != 0 {
+                survival[neighbours]
+            } else {
+                birth[neighbours]
+            }
+        })
+        .collect())
 }
 
-fn _print_map(map: &FnvHashSet<(i32, i32)>) {
-    let x_max = map.iter().map(|(x, _y)| *x).max().unwrap();
-    let x_min = map.iter().map(|(x, _y)| *x).min().unwrap();
-    let y_max = map.iter().map(|(_x, y)| *y).max().unwrap();
-    let y_min = map.iter().map(|(_x, y)| *y).min().unwrap();
+impl Display for Image {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for y in 0..self.height as isize {
+            for x in 0..self.width as isize {
+                write!(f, "{}", if self.get(x, y) { '#' } else { '.' })?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
 
-    println!("Printing map with dimensions [{x_min},{y_min}]..[{x_max},{y_max}]");
+impl Input {
+    pub fn new(light_lookup: Vec<bool>, initial_image: Image) -> Result<Input, Error> {
+        if light_lookup.len() != 512 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "The lookup needs an entry for every 3x3 square, not {}",
+                    light_lookup.len()
+                ),
+            ));
+        }
+        Ok(Input {
+            light_lookup,
+            initial_image,
+        })
+    }
 
-    for x in x_min..=x_max {
-        for y in y_min..=y_max {
-            if map.contains(&(x, y)) {
-                print!("#");
-            } else {
-                print!(".");
+    /// Reads a pattern in run length encoding, stepped by the rule in its header or else by
+    /// `B3/S23`, Conway's Game of Life.
+    pub fn from_rle(rle: &str) -> Result<Input, Error> {
+        let error = |reason: String| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid RLE pattern: {reason}"),
+            )
+        };
+        let mut lines = rle.lines().filter(|line| !line.starts_with('#'));
+        let header = lines
+            .next()
+            .ok_or_else(|| error("missing header".to_string()))?;
+        let (mut width, mut height, mut rule) = (None, None, "B3/S23");
+        for entry in header.split(',') {
+            let (key, value) = entry
+                .split_once('=')
+                .ok_or_else(|| error(format!("expected key = value in header {header}")))?;
+            let size = || {
+                value
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|_| error(format!("invalid size {value} in header {header}")))
+            };
+            match key.trim() {
+                "x" => width = Some(size()?),
+                "y" => height = Some(size()?),
+                "rule" => rule = value.trim(),
+                _ => return Err(error(format!("unknown key {key} in header {header}"))),
             }
         }
-        print!("\n");
+        let (width, height) = match (width, height) {
+            (Some(width), Some(height)) => (width, height),
+            _ => return Err(error(format!("expected x and y in header {header}"))),
+        };
+
+        let mut image = Image::new(width, height, false);
+        let (mut x, mut y, mut count) = (0, 0, None);
+        for char in lines.flat_map(|line| line.chars()) {
+            if let Some(digit) = char.to_digit(10) {
+                count = Some(count.unwrap_or(0) * 10 + digit as usize);
+                continue;
+            }
+            let run = count.take().unwrap_or(1);
+            match char {
+                'b' | 'o' => {
+                    if x + run > width || y >= height {
+                        return Err(error(format!("run beyond {width}x{height} in row {y}")));
+                    }
+                    for x in x..x + run {
+                        image.set(x, y, char == 'o');
+                    }
+                    x += run;
+                }
+                '$' => {
+                    x = 0;
+                    y += run;
+                }
+                '!' => return Input::new(life_rule(rule)?, image),
+                char if char.is_whitespace() => {}
+                char => return Err(error(format!("unexpected '{char}' in row {y}"))),
+            }
+        }
+        Err(error("missing '!' at the end".to_string()))
+    }
+
+    /// The puzzle's lookup line with the image read from a PBM or PGM file.
+    pub fn load(lookup: &str, image: &Path) -> Result<Input, Error> {
+        let lookup = lookup.trim().chars().map(|c| c == '#').collect();
+        Input::new(lookup, Image::from_pnm(&fs::read(image)?)?)
+    }
+
+    /// Saves the image before and after each of `steps` enhancements as numbered frames like
+    /// `frame_000.pbm` in `dir`, all as large as the last one. `extension` picks the format as
+    /// in `Image::save`.
+    pub fn save_frames(
+        &self,
+        steps: usize,
+        dir: &Path,
+        extension: &str,
+    ) -> Result<Vec<PathBuf>, Error> {
+        fs::create_dir_all(dir)?;
+        let digits = steps.to_string().len().max(3);
+        let mut image = self.initial_image.clone();
+        let mut frames = Vec::with_capacity(steps + 1);
+        for step in 0..=steps {
+            if step > 0 {
+                image = image.enhance(&self.light_lookup);
+            }
+            let path = dir.join(format!("frame_{step:0digits$}.{extension}"));
+            image.padded(steps - step).save(&path)?;
+            frames.push(path);
+        }
+        Ok(frames)
+    }
+
+    pub fn enhance(&self, steps: usize) -> Image {
+        let mut image = self.initial_image.clone();
+        for _ in 0..steps {
+            image = image.enhance(&self.light_lookup);
+        }
+        image
+    }
+}
+
+pub fn generator(input: &str) -> Input {
+    let mut lines = input.lines();
+    let lookup: Vec<bool> = lines.next().unwrap().chars().map(|c| c == '#').collect();
+    assert_eq!(
+        512,
+        lookup.len(),
+        "the lookup needs an entry for every 3x3 square"
+    );
+    lines.next();
+    let rows: Vec<&str> = lines.collect();
+    // the image may also be a plain PBM or PGM
+    if rows
+        .first()
+        .is_some_and(|row| row.starts_with("P1") || row.starts_with("P2"))
+    {
+        let image = Image::from_pnm(rows.join("\n").as_bytes()).expect("invalid image");
+        return Input::new(lookup, image).unwrap();
+    }
+    let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    let mut initial_image = Image::new(width, rows.len(), false);
+    for (y, row) in rows.iter().enumerate() {
+        for (x, char) in row.chars().enumerate() {
+            initial_image.set(x, y, char == '#');
+        }
+    }
+
+    Input {
+        light_lookup: lookup,
+        initial_image,
     }
 }
 
+pub fn part_1(input: &Input) -> usize {
+    input
+        .enhance(2)
+        .lit_pixels()
+        .expect("infinitely many lit pixels")
+}
+
+pub fn part_2(input: &Input) -> usize {
+    input
+        .enhance(50)
+        .lit_pixels()
+        .expect("infinitely many lit pixels")
+}
+
+fn _print_map(image: &Image) {
+    println!(
+        "Printing map with dimensions {}x{} on a {} background",
+        image.width,
+        image.height,
+        if image.background { "lit" } else { "dark" }
+    );
+    print!("{image}");
+}
+
 #[test]
 fn test() {
     let input = "..#.#..#####.#.#.#.###.##.....###.##.#..###.####..#####..#....#..#..##..##\
@@ -167,4 +595,168 @@ fn test() {
     ..###";
 
     assert_eq!(35, part_1(&generator(&input)));
+    assert_eq!(3351, part_2(&generator(input)));
+    assert_eq!(
+        ".##.##.\n#..#.#.\n##.#..#\n####..#\n.#..##.\n..##..#\n...#.#.\n",
+        generator(input).enhance(1).to_string()
+    );
+}
+
+#[test]
+fn test_background() {
+    // only the entries for all dark and all lit neighbourhoods differ between the lookups
+    let image = |first: char, last: char| {
+        let lookup = format!("{first}{}{last}", ".".repeat(510));
+        generator(&format!("{lookup}\n\n.#.\n...\n..."))
+    };
+
+    let dark = image('.', '#');
+    assert_eq!(Some(0), dark.enhance(1).lit_pixels());
+    assert!(!dark.enhance(7).background());
+
+    let flashing = image('#', '.');
+    assert_eq!(None, flashing.enhance(1).lit_pixels());
+    assert_eq!(
+        "#...#\n#...#\n#...#\n#####\n#####\n",
+        flashing.enhance(1).to_string()
+    );
+    // only the middle of the dark square sees nothing lit
+    assert_eq!(Some(1), flashing.enhance(2).lit_pixels());
+    assert!(flashing.enhance(3).background());
+
+    let lit = image('#', '#');
+    assert_eq!(None, lit.enhance(1).lit_pixels());
+    assert_eq!(None, lit.enhance(2).lit_pixels());
+
+    // rows wider than a word of bits, with lookups copying the middle or left neighbour
+    let row = "#".repeat(64) + "." + &"#".repeat(5);
+    let copy = |bit| {
+        let lookup: String = (0..512)
+            .map(|idx| if idx & 1 << bit != 0 { '#' } else { '.' })
+            .collect();
+        generator(&format!("{lookup}\n\n{row}"))
+    };
+    let middle = copy(4).enhance(2);
+    assert_eq!(Some(69), middle.lit_pixels());
+    assert_eq!(
+        format!("..{row}.."),
+        middle.to_string().lines().nth(2).unwrap()
+    );
+    let left = copy(5).enhance(1);
+    assert_eq!(format!("..{row}"), left.to_string().lines().nth(1).unwrap());
+}
+
+#[test]
+fn test_life() {
+    let life = life_rule("B3/S23").unwrap();
+    assert_eq!(life, life_rule("s23/b3").unwrap());
+    assert!(!life[0b000_010_000]);
+    assert!(life[0b000_010_011]);
+    assert!(life[0b100_000_011]);
+    assert!(!life[0b101_010_011]);
+    assert_eq!(
+        "Invalid rule B9/S23: '9' is not a count of 0 to 8 neighbours",
+        life_rule("B9/S23").unwrap_err().to_string()
+    );
+    assert!(life_rule("B3").is_err());
+    assert!(life_rule("B3/S2/S3").is_err());
+
+    let glider = Input::from_rle(
+        "#N Glider\n\
+        x = 3, y = 3, rule = B3/S23\n\
+        bob$2bo$3o!",
+    )
+    .unwrap();
+    assert_eq!(".#.\n..#\n###\n", glider.initial_image.to_string());
+    assert_eq!("#.#\n.##\n.#.\n", glider.enhance(1).trimmed().to_string());
+    assert_eq!(glider.initial_image, glider.enhance(4).trimmed());
+    assert_eq!(Some(5), glider.enhance(40).lit_pixels());
+
+    let blinker = Input::from_rle("x = 3, y = 1\n3o!").unwrap();
+    assert_eq!("#\n#\n#\n", blinker.enhance(1).trimmed().to_string());
+    assert_eq!("###\n", blinker.enhance(2).trimmed().to_string());
+
+    // B0 rules light up the background, which the lookup has to turn off again
+    let inverted = Input::from_rle("x = 2, y = 2, rule = B0/S\n2o$2o!").unwrap();
+    assert!(inverted.enhance(1).background());
+    assert!(!inverted.enhance(2).background());
+
+    assert!(Input::from_rle("x = 2, y = 1\n3o!").is_err());
+    assert!(Input::from_rle("x = 2, y = 1\n2o").is_err());
+    assert_eq!(
+        "Invalid RLE pattern: unexpected 'x' in row 0",
+        Input::from_rle("x = 2, y = 1\nox!")
+            .err()
+            .unwrap()
+            .to_string()
+    );
+}
+
+#[test]
+fn test_pnm() {
+    let lookup = "..#.#..#####.#.#.#.###.##.....###.##.#..###.####..#####..#....#..#..##..##\
+    #..######.###...####..#..#####..##..#.#####...##.#.#..#.##..#.#......#.###\
+    .######.###.####...#.##.##..#..#..#####.....#.#....###..#.##......#.....#.\
+    .#..#..##..#...##.######.####.####.#.#...#.......#..#.#.#...####.##.#.....\
+    .#..#...##.#.##..#...##.#.##..###.#......#.#.......#.#.#.####.###.##...#..\
+    ...####.#..#..#.##.#....##..#.####....##...##..#...#......#.#.......#.....\
+    ..##..####..#...#.#.#...##..#.#..###..#####........#..####......#..#";
+    let input = generator(&format!("{lookup}\n\n#..#.\n#....\n##..#\n..#..\n..###"));
+    let pbm = input.initial_image.to_pbm();
+    assert_eq!("P1\n5 5\n10010\n10000\n11001\n00100\n00111\n", pbm);
+    assert_eq!(35, part_1(&generator(&format!("{lookup}\n\n{pbm}"))));
+
+    let image = input.enhance(2);
+    assert_eq!(image, Image::from_pnm(image.to_pbm().as_bytes()).unwrap());
+    assert_eq!(image, Image::from_pnm(image.to_pgm().as_bytes()).unwrap());
+    assert!(image.to_pgm().lines().all(|line| line.len() <= 70));
+    let wide = Image::new(100, 1, false).to_pbm();
+    assert_eq!(
+        vec![70, 30],
+        wide.lines().skip(2).map(str::len).collect::<Vec<_>>()
+    );
+
+    // raw images with a comment in the header, PBM rows are padded to whole bytes
+    let mut raw = b"P4\n# made by hand\n10 2\n".to_vec();
+    raw.extend([0b1000_0000, 0b0100_0000, 0b0000_0000, 0b1111_1111]);
+    assert_eq!(
+        "#........#\n........##\n",
+        Image::from_pnm(&raw).unwrap().to_string()
+    );
+    let mut raw = b"P5 3 1 1000\n".to_vec();
+    raw.extend([0, 0, 0x01, 0xf4, 0x03, 0xe8]);
+    assert_eq!("#..\n", Image::from_pnm(&raw).unwrap().to_string());
+    assert_eq!(
+        "Invalid PNM image at byte 0: expected P1, P2, P4 or P5",
+        Image::from_pnm(b"P3 1 1 1 1").unwrap_err().to_string()
+    );
+    assert_eq!(
+        "Invalid PNM image at byte 0: expected P1, P2, P4 or P5",
+        Image::from_pnm(b"").unwrap_err().to_string()
+    );
+    assert_eq!(
+        "Invalid PNM image at byte 0: expected P1, P2, P4 or P5",
+        Image::from_pnm(b"GIF89a").unwrap_err().to_string()
+    );
+    assert_eq!(
+        "Invalid PNM image at byte 2: expected a number",
+        Image::from_pnm(b"P1").unwrap_err().to_string()
+    );
+    assert!(Image::from_pnm(b"P1 2 2 1 0 1").is_err());
+
+    let dir = std::env::temp_dir().join(format!("day20_frames_{}", std::process::id()));
+    let frames = input.save_frames(2, &dir, "pbm").unwrap();
+    assert_eq!(
+        vec!["frame_000.pbm", "frame_001.pbm", "frame_002.pbm"],
+        frames
+            .iter()
+            .map(|frame| frame.file_name().unwrap().to_str().unwrap())
+            .collect::<Vec<_>>()
+    );
+    let first = Image::from_pnm(&fs::read(&frames[0]).unwrap()).unwrap();
+    assert_eq!(input.initial_image.padded(2), first);
+    assert_eq!((9, 9), (first.width(), first.height()));
+    let loaded = Input::load(lookup, &frames[2]).unwrap();
+    assert_eq!(image, loaded.initial_image);
+    fs::remove_dir_all(dir).unwrap();
 }
@@ -1,6 +1,16 @@
+use std::{
+    fmt::Write,
+    io::{Error, ErrorKind},
+};
+
 use fnv::FnvHashMap;
 use itertools::Itertools;
 use parse_display::{Display, FromStr};
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    rngs::StdRng,
+    Rng, SeedableRng,
+};
 
 #[derive(Debug, Display, FromStr, Clone, Copy)]
 #[display("Player {_id} starting position: {pos}")]
@@ -11,154 +21,496 @@ pub struct Player {
     score: u32,
 }
 
-pub fn generator(input: &str) -> Vec<Player> {
-    input.lines().map(|p| p.parse().unwrap()).collect()
+/// The rules of a game of dice on a circular track of spaces numbered from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiceGame {
+    pub board_size: u32,
+    /// the die shows 1 up to this
+    pub die_sides: u32,
+    pub rolls_per_turn: u32,
+    /// the first player to reach this score wins
+    pub target_score: u32,
+    pub players: usize,
 }
 
-pub fn part_1(val: &[Player]) -> u32 {
-    let mut p1 = val[0];
-    let mut p2 = val[1];
-
-    (1..)
-        .tuples()
-        .zip(1..)
-        .flat_map(|((a, b, c), t)| easy_player_turn(a + b + c, &mut p1, &mut p2, t))
-        .next()
-        .unwrap()
+/// The practice game of part 1, played with a deterministic 100-sided die.
+pub const PRACTICE: DiceGame = DiceGame {
+    board_size: 10,
+    die_sides: 100,
+    rolls_per_turn: 3,
+    target_score: 1000,
+    players: 2,
+};
+
+/// The game of part 2, played with the 3-sided Dirac die.
+pub const DIRAC: DiceGame = DiceGame {
+    target_score: 21,
+    die_sides: 3,
+    ..PRACTICE
+};
+
+impl DiceGame {
+    /// Number of ways each sum of one turn's rolls comes up, indexed by the sum.
+    pub fn roll_sums(&self) -> Vec<u64> {
+        let mut sums = vec![1];
+        for _ in 0..self.rolls_per_turn {
+            let mut next = vec![0; sums.len() + self.die_sides as usize];
+            for (sum, &count) in sums.iter().enumerate() {
+                for eyes in 1..=self.die_sides as usize {
+                    next[sum + eyes] += count;
+                }
+            }
+            sums = next;
+        }
+        sums
+    }
+
+    /// Space reached by moving `eyes` forward from `pos`.
+    fn advance(&self, pos: u32, eyes: u32) -> u32 {
+        (pos - 1 + eyes % self.board_size) % self.board_size + 1
+    }
+
+    fn check(&self, players: &[Player]) -> Result<(), Error> {
+        if self.board_size == 0
+            || self.die_sides == 0
+            || self.rolls_per_turn == 0
+            || self.players == 0
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Cannot play without board spaces, die sides, rolls or players: {self:?}"),
+            ));
+        }
+        if players.len() != self.players {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Game for {} players started with {}",
+                    self.players,
+                    players.len()
+                ),
+            ));
+        }
+        match players
+            .iter()
+            .find(|p| p.pos == 0 || p.pos > self.board_size)
+        {
+            Some(p) => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("{p} is not on a board of {} spaces", self.board_size),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Plays with a deterministic die that counts up from 1 and wraps around after its last
+    /// side. Returns the number of rolls times the lowest score of the losing players.
+    pub fn practice(&self, players: &[Player]) -> Result<u32, Error> {
+        let mut die = DeterministicDie::new(self.die_sides);
+        Ok(self.simulate(players, &mut die)?.outcome())
+    }
+
+    /// Plays a game with `die`, keeping a log of every turn.
+    pub fn simulate<D: Die + ?Sized>(
+        &self,
+        players: &[Player],
+        die: &mut D,
+    ) -> Result<GameLog, Error> {
+        self.check(players)?;
+        let mut players = players.to_vec();
+        let mut log = GameLog {
+            turns: Vec::new(),
+            rolls: 0,
+            scores: Vec::new(),
+            winner: 0,
+        };
+        for current in (0..players.len()).cycle() {
+            let rolls: Vec<u32> = (0..self.rolls_per_turn).map(|_| die.roll()).collect();
+            log.rolls += self.rolls_per_turn;
+            let player = &mut players[current];
+            let won = easy_player_turn(self, rolls.iter().sum(), player);
+            log.turns.push(Turn {
+                player: current,
+                rolls,
+                pos: player.pos,
+                score: player.score,
+            });
+            if won {
+                log.winner = current;
+                break;
+            }
+        }
+        log.scores = players.iter().map(|p| p.score).collect();
+        Ok(log)
+    }
+
+    /// Number of universes each player wins in when every roll splits the universe once for
+    /// every side of the die.
+    pub fn wins(&self, players: &[Player]) -> Result<Vec<u64>, Error> {
+        self.check(players)?;
+        let roll_sums = self.roll_sums();
+
+        let mut curr_gen = FnvHashMap::default();
+        curr_gen.insert(Universe::new(players), 1);
+
+        let mut won_universes = vec![0u64; players.len()];
+
+        while !curr_gen.is_empty() {
+            let mut next_gen = FnvHashMap::default();
+
+            for (universe, count) in curr_gen.iter() {
+                for (new_universe, count) in universe.expand(self, &roll_sums, *count) {
+                    match new_universe.has_winner(self) {
+                        WinState::Winner(player) => won_universes[player] += count,
+                        WinState::NotYetDecided => {
+                            *next_gen.entry(new_universe).or_insert(0) += count
+                        }
+                    }
+                }
+            }
+            curr_gen = next_gen;
+        }
+
+        Ok(won_universes)
+    }
+
+    /// Like `wins`, but recursing into every state the game can reach from the start, to find
+    /// out how it goes on from there.
+    pub fn analyse(&self, players: &[Player]) -> Result<Analysis, Error> {
+        self.check(players)?;
+        let roll_sums = self.roll_sums();
+        let start = Universe::new(players);
+        let mut states = FnvHashMap::default();
+        self.analyse_state(&start, &roll_sums, &mut states);
+        Ok(Analysis {
+            game: *self,
+            start,
+            states,
+        })
+    }
+
+    fn analyse_state(
+        &self,
+        universe: &Universe,
+        roll_sums: &[u64],
+        states: &mut FnvHashMap<Universe, StateStats>,
+    ) {
+        let total: u64 = roll_sums.iter().sum();
+        let mut stats = StateStats {
+            wins: vec![0; self.players],
+            win_probability: vec![0.0; self.players],
+            lengths: vec![0],
+            expected_turns: 0.0,
+        };
+        for (new_universe, count) in universe.expand(self, roll_sums, 1) {
+            let probability = count as f64 / total as f64;
+            if let WinState::Winner(player) = new_universe.has_winner(self) {
+                stats.wins[player] += count;
+                stats.win_probability[player] += probability;
+                add_lengths(&mut stats.lengths, &[1], count);
+                stats.expected_turns += probability;
+                continue;
+            }
+            if !states.contains_key(&new_universe) {
+                self.analyse_state(&new_universe, roll_sums, states);
+            }
+            let next = &states[&new_universe];
+            for player in 0..self.players {
+                stats.wins[player] += count * next.wins[player];
+                stats.win_probability[player] += probability * next.win_probability[player];
+            }
+            add_lengths(&mut stats.lengths, &next.lengths, count);
+            stats.expected_turns += probability * (1.0 + next.expected_turns);
+        }
+        states.insert(universe.clone(), stats);
+    }
+}
+
+/// Adds `count` times `lengths`, one turn later, to `into`.
+fn add_lengths(into: &mut Vec<u64>, lengths: &[u64], count: u64) {
+    if into.len() < lengths.len() + 1 {
+        into.resize(lengths.len() + 1, 0);
+    }
+    for (turns, universes) in lengths.iter().enumerate() {
+        into[turns + 1] += count * universes;
+    }
+}
+
+/// Something to roll, showing a number from 1 up to its number of sides.
+pub trait Die {
+    fn roll(&mut self) -> u32;
+}
+
+/// Counts up from 1 and starts over after its last side.
+#[derive(Debug, Clone)]
+pub struct DeterministicDie {
+    sides: u32,
+    last: u32,
+}
+
+impl DeterministicDie {
+    pub fn new(sides: u32) -> DeterministicDie {
+        DeterministicDie { sides, last: 0 }
+    }
+}
+
+impl Die for DeterministicDie {
+    fn roll(&mut self) -> u32 {
+        self.last = self.last % self.sides + 1;
+        self.last
+    }
 }
 
-fn easy_player_turn(
-    eyes: u32,
-    player_1: &mut Player,
-    player_2: &mut Player,
-    turn: u32,
-) -> Option<u32> {
-    let player_turn = |p1: &mut Player, p2: &mut Player, eyes, turn| {
-        let next_pos = p2.pos + eyes;
-        let next_pos = next_pos - ((next_pos - 1) / 10) * 10;
-        p2.pos = next_pos;
-        p2.score += p2.pos;
-        if p2.score >= 1000 {
-            Some(turn * 3 * p1.score)
-        } else {
-            None
+/// A fair die whose rolls repeat for the same seed.
+#[derive(Debug, Clone)]
+pub struct SeededDie {
+    sides: u32,
+    rng: StdRng,
+}
+
+impl SeededDie {
+    pub fn new(sides: u32, seed: u64) -> SeededDie {
+        SeededDie {
+            sides,
+            rng: StdRng::seed_from_u64(seed),
         }
-    };
+    }
+}
+
+impl Die for SeededDie {
+    fn roll(&mut self) -> u32 {
+        self.rng.gen_range(1..=self.sides)
+    }
+}
+
+/// Shows side `n` with a probability proportional to `weights[n - 1]`.
+#[derive(Debug, Clone)]
+pub struct LoadedDie {
+    sides: WeightedIndex<u32>,
+    rng: StdRng,
+}
+
+impl LoadedDie {
+    pub fn new(weights: &[u32], seed: u64) -> Result<LoadedDie, Error> {
+        let sides = WeightedIndex::new(weights).map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Cannot load a die with weights {weights:?}: {e}"),
+            )
+        })?;
+        Ok(LoadedDie {
+            sides,
+            rng: StdRng::seed_from_u64(seed),
+        })
+    }
+}
 
-    if turn % 2 == 0 {
-        player_turn(player_1, player_2, eyes, turn)
-    } else {
-        player_turn(player_2, player_1, eyes, turn)
+impl Die for LoadedDie {
+    fn roll(&mut self) -> u32 {
+        self.sides.sample(&mut self.rng) as u32 + 1
     }
 }
 
-#[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
+/// One player's turn, with the position and score it ended at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Turn {
+    pub player: usize,
+    pub rolls: Vec<u32>,
+    pub pos: u32,
+    pub score: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameLog {
+    pub turns: Vec<Turn>,
+    /// times the die was rolled
+    pub rolls: u32,
+    /// final score of every player
+    pub scores: Vec<u32>,
+    pub winner: usize,
+}
+
+impl GameLog {
+    /// The number of rolls times the lowest score of the losing players.
+    pub fn outcome(&self) -> u32 {
+        let loser = self
+            .scores
+            .iter()
+            .enumerate()
+            .filter(|(player, _)| *player != self.winner)
+            .map(|(_, score)| *score)
+            .min()
+            .unwrap_or(0);
+        self.rolls * loser
+    }
+}
+
+pub fn generator(input: &str) -> Vec<Player> {
+    input.lines().map(|p| p.parse().unwrap()).collect()
+}
+
+pub fn part_1(val: &[Player]) -> u32 {
+    PRACTICE.practice(val).unwrap()
+}
+
+/// Moves `player` by `eyes` and tells whether that won the game.
+fn easy_player_turn(game: &DiceGame, eyes: u32, player: &mut Player) -> bool {
+    player.pos = game.advance(player.pos, eyes);
+    player.score += player.pos;
+    player.score >= game.target_score
+}
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone)]
 pub struct Universe {
-    total_value_a: u8,
-    total_value_b: u8,
-    curr_pos_a: u8,
-    curr_pos_b: u8,
+    /// position and score of every player
+    players: Vec<(u32, u32)>,
+    /// the player to roll next
+    next: usize,
 }
 
 impl Universe {
-    pub fn new(total_value_a: u8, total_value_b: u8, curr_pos_a: u8, curr_pos_b: u8) -> Universe {
+    /// Position and score of every player.
+    pub fn players(&self) -> &[(u32, u32)] {
+        &self.players
+    }
+
+    pub fn next_player(&self) -> usize {
+        self.next
+    }
+
+    pub fn new(players: &[Player]) -> Universe {
         Universe {
-            total_value_a,
-            total_value_b,
-            curr_pos_a,
-            curr_pos_b,
+            players: players.iter().map(|p| (p.pos, p.score)).collect(),
+            next: 0,
         }
     }
 
-    pub fn expand(&self, quantity: u64) -> Vec<(Universe, u64)> {
-        let mut universes = Vec::with_capacity(49);
-        let universes_by_eyes = |eyes| match eyes {
-            3 | 9 => 1,
-            4 | 8 => 3,
-            5 | 7 => 6,
-            6 => 7,
-            _ => unreachable!(),
-        };
-        for a_eyes in 3..=9 {
-            let next_pos_a = &self.curr_pos_a + a_eyes;
-            let next_pos_a = next_pos_a - ((next_pos_a - 1) / 10) * 10;
-            assert!(next_pos_a > 0 && next_pos_a < 11);
-            let next_total_a = &self.total_value_a + next_pos_a;
-            if next_total_a >= 21 {
-                let created_universe = Universe::new(next_total_a, 0, next_pos_a, 0);
-                universes.push((created_universe, universes_by_eyes(a_eyes) * quantity));
-            } else {
-                for b_eyes in 3..=9 {
-                    let next_pos_b = &self.curr_pos_b + b_eyes;
-                    let next_pos_b = next_pos_b - ((next_pos_b - 1) / 10) * 10;
-                    assert!(next_pos_b > 0 && next_pos_b < 11);
-                    let next_total_b = &self.total_value_b + next_pos_b;
-                    let created_universe =
-                        Universe::new(next_total_a, next_total_b, next_pos_a, next_pos_b);
-                    universes.push((
-                        created_universe,
-                        universes_by_eyes(a_eyes) * quantity * universes_by_eyes(b_eyes),
-                    ))
-                }
-            }
+    /// The universes after the next player's turn, each with the `quantity` of universes it
+    /// stands for.
+    pub fn expand(
+        &self,
+        game: &DiceGame,
+        roll_sums: &[u64],
+        quantity: u64,
+    ) -> Vec<(Universe, u64)> {
+        let mut universes = Vec::with_capacity(roll_sums.len());
+        let (pos, score) = self.players[self.next];
+        for (eyes, &count) in roll_sums.iter().enumerate().filter(|(_, &c)| c > 0) {
+            let next_pos = game.advance(pos, eyes as u32);
+            let mut created_universe = Universe {
+                players: self.players.clone(),
+                next: (self.next + 1) % self.players.len(),
+            };
+            created_universe.players[self.next] = (next_pos, score + next_pos);
+            universes.push((created_universe, count * quantity));
         }
 
         universes
     }
 
-    fn has_winner(&self) -> WinState {
-        if self.total_value_a >= 21 {
-            WinState::WinnerA
-        } else if self.total_value_b >= 21 {
-            WinState::WinnerB
-        } else {
-            WinState::NotYetDecided
+    fn has_winner(&self, game: &DiceGame) -> WinState {
+        match self
+            .players
+            .iter()
+            .position(|&(_, score)| score >= game.target_score)
+        {
+            Some(player) => WinState::Winner(player),
+            None => WinState::NotYetDecided,
         }
     }
 }
 
 #[derive(Debug, PartialEq, Eq)]
 enum WinState {
-    WinnerA,
-    WinnerB,
+    Winner(usize),
     NotYetDecided,
 }
 
-pub fn part_2(val: &[Player]) -> u64 {
-    let p1 = val[0];
-    let p2 = val[1];
+/// How the game goes on from a state in which no one has won yet. Turns count the moves of
+/// single players.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateStats {
+    /// universes each player wins in
+    pub wins: Vec<u64>,
+    pub win_probability: Vec<f64>,
+    /// universes by the number of turns until someone wins
+    pub lengths: Vec<u64>,
+    pub expected_turns: f64,
+}
 
-    let mut curr_gen = FnvHashMap::default();
-    curr_gen.insert(
-        Universe::new(0, 0, p1.pos.try_into().unwrap(), p2.pos.try_into().unwrap()),
-        1,
-    );
+/// The statistics of every undecided state reachable in a game.
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    game: DiceGame,
+    start: Universe,
+    states: FnvHashMap<Universe, StateStats>,
+}
 
-    let mut a_won_universes = 0u64;
-    let mut b_won_universes = 0u64;
+impl Analysis {
+    pub fn start(&self) -> &StateStats {
+        &self.states[&self.start]
+    }
 
-    loop {
-        let mut next_gen = FnvHashMap::default();
+    pub fn state(&self, universe: &Universe) -> Option<&StateStats> {
+        self.states.get(universe)
+    }
 
-        for (universe, count) in curr_gen.iter_mut() {
-            for (new_universe, count) in universe.expand(*count) {
-                match new_universe.has_winner() {
-                    WinState::WinnerA => a_won_universes += count,
-                    WinState::WinnerB => b_won_universes += count,
-                    WinState::NotYetDecided => {
-                        *next_gen.entry(new_universe).or_insert(0) += count
-                    }
-                }
-            }
+    pub fn states(&self) -> impl Iterator<Item = (&Universe, &StateStats)> {
+        self.states.iter()
+    }
+
+    /// Probability of the game ending after each number of turns, a universe in which it takes
+    /// `n` turns stands for one of `total^n` equally likely outcomes.
+    pub fn length_probability(&self) -> Vec<f64> {
+        let total = self.game.roll_sums().iter().sum::<u64>() as f64;
+        self.start()
+            .lengths
+            .iter()
+            .enumerate()
+            .map(|(turns, &universes)| universes as f64 / total.powi(turns as i32))
+            .collect()
+    }
 
+    /// One line for each state, ordered by the player to move next and then by positions and
+    /// scores.
+    pub fn to_csv(&self) -> String {
+        let players = self.start.players.len();
+        let mut csv = "next".to_string();
+        for player in 1..=players {
+            write!(csv, ",pos_{player},score_{player}").unwrap();
+        }
+        for player in 1..=players {
+            write!(csv, ",wins_{player}").unwrap();
         }
-        if next_gen.is_empty() {
-            break;
+        for player in 1..=players {
+            write!(csv, ",win_probability_{player}").unwrap();
         }
-        curr_gen = next_gen;
+        csv.push_str(",expected_turns\n");
+
+        let sorted = self
+            .states
+            .iter()
+            .map(|(universe, stats)| ((universe.next, &universe.players), stats))
+            .sorted_by(|(a, _), (b, _)| a.cmp(b));
+        for ((next, positions), stats) in sorted {
+            write!(csv, "{}", next + 1).unwrap();
+            for (pos, score) in positions {
+                write!(csv, ",{pos},{score}").unwrap();
+            }
+            for wins in &stats.wins {
+                write!(csv, ",{wins}").unwrap();
+            }
+            for probability in &stats.win_probability {
+                write!(csv, ",{probability}").unwrap();
+            }
+            writeln!(csv, ",{}", stats.expected_turns).unwrap();
+        }
+        csv
     }
+}
 
-    a_won_universes.max(b_won_universes)
+pub fn part_2(val: &[Player]) -> u64 {
+    DIRAC.wins(val).unwrap().into_iter().max().unwrap()
 }
 
 #[test]
@@ -169,3 +521,158 @@ fn test() {
     assert_eq!(739785, part_1(&generator(&input)));
     assert_eq!(444356092776315, part_2(&generator(&input)));
 }
+
+#[test]
+fn test_dice_game() {
+    let players = generator("Player 1 starting position: 4\nPlayer 2 starting position: 8");
+    assert_eq!(vec![0, 0, 0, 1, 3, 6, 7, 6, 3, 1], DIRAC.roll_sums());
+    assert_eq!(
+        vec![444356092776315, 341960390180808],
+        DIRAC.wins(&players).unwrap()
+    );
+
+    // a two-sided die rolled once per turn on a board of two spaces
+    let coin = DiceGame {
+        board_size: 2,
+        die_sides: 2,
+        rolls_per_turn: 1,
+        target_score: 3,
+        players: 2,
+    };
+    assert_eq!(vec![0, 1, 1], coin.roll_sums());
+    let players = generator("Player 1 starting position: 1\nPlayer 2 starting position: 2");
+    // rolls 1, 2, 1: player 1 scores 2 and 1, player 2 stays at 2
+    assert_eq!(3 * 2, coin.practice(&players).unwrap());
+    // player 1 wins all 4 universes where it first rolls a 1, and 4 of the 7 where it doesn't
+    assert_eq!(vec![8, 3], coin.wins(&players).unwrap());
+
+    let three = DiceGame {
+        players: 3,
+        ..PRACTICE
+    };
+    let players = generator(
+        "Player 1 starting position: 4\n\
+        Player 2 starting position: 8\n\
+        Player 3 starting position: 1",
+    );
+    assert!(PRACTICE.practice(&players).is_err());
+    assert!(three.practice(&players).is_ok());
+    assert!(three.wins(&players[..2]).is_err());
+    let nobody = DiceGame {
+        players: 0,
+        ..DIRAC
+    };
+    assert!(nobody.wins(&[]).is_err());
+    assert!(nobody.analyse(&[]).is_err());
+    assert!(nobody.practice(&[]).is_err());
+    let off_board = generator("Player 1 starting position: 11\nPlayer 2 starting position: 8");
+    assert_eq!(
+        "Player 1 starting position: 11 is not on a board of 10 spaces",
+        DIRAC.wins(&off_board).unwrap_err().to_string()
+    );
+}
+
+#[test]
+fn test_analysis() {
+    let players = generator("Player 1 starting position: 4\nPlayer 2 starting position: 8");
+    let analysis = DIRAC.analyse(&players).unwrap();
+    let start = analysis.start();
+    assert_eq!(DIRAC.wins(&players).unwrap(), start.wins);
+    assert_eq!(
+        start.wins.iter().sum::<u64>(),
+        start.lengths.iter().sum::<u64>()
+    );
+    assert!((start.win_probability.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+    let expected = analysis
+        .length_probability()
+        .iter()
+        .enumerate()
+        .map(|(turns, probability)| turns as f64 * probability)
+        .sum::<f64>();
+    assert!((start.expected_turns - expected).abs() < 1e-9);
+
+    // the coin game of `test_dice_game`: player 1 wins at turn 3 with probability 1/2 + 1/8 +
+    // 1/8, player 2 at turn 4 with probability 1/16 + 1/8, and player 1 at turn 5 with 1/16
+    let coin = DiceGame {
+        board_size: 2,
+        die_sides: 2,
+        rolls_per_turn: 1,
+        target_score: 3,
+        players: 2,
+    };
+    let players = generator("Player 1 starting position: 1\nPlayer 2 starting position: 2");
+    let analysis = coin.analyse(&players).unwrap();
+    let start = analysis.start();
+    assert_eq!(vec![8, 3], start.wins);
+    assert_eq!(vec![0, 0, 0, 6, 3, 2], start.lengths);
+    assert_eq!(vec![0.8125, 0.1875], start.win_probability);
+    assert_eq!(
+        vec![0.0, 0.0, 0.0, 0.75, 0.1875, 0.0625],
+        analysis.length_probability()
+    );
+    assert_eq!(3.3125, start.expected_turns);
+
+    let csv = analysis.to_csv();
+    let mut lines = csv.lines();
+    assert_eq!(
+        Some("next,pos_1,score_1,pos_2,score_2,wins_1,wins_2,win_probability_1,win_probability_2,expected_turns"),
+        lines.next()
+    );
+    assert_eq!(Some("1,1,0,2,0,8,3,0.8125,0.1875,3.3125"), lines.next());
+    assert_eq!(analysis.states().count(), csv.lines().count() - 1);
+    let state = analysis
+        .states()
+        .find(|(u, _)| u.next_player() == 1)
+        .unwrap()
+        .0;
+    assert!(analysis.state(state).is_some());
+}
+
+#[test]
+fn test_dice() {
+    let players = generator("Player 1 starting position: 4\nPlayer 2 starting position: 8");
+    let log = PRACTICE
+        .simulate(&players, &mut DeterministicDie::new(100))
+        .unwrap();
+    let turn = |player, rolls: [u32; 3], pos, score| Turn {
+        player,
+        rolls: rolls.to_vec(),
+        pos,
+        score,
+    };
+    assert_eq!(
+        vec![
+            turn(0, [1, 2, 3], 10, 10),
+            turn(1, [4, 5, 6], 3, 3),
+            turn(0, [7, 8, 9], 4, 14),
+            turn(1, [10, 11, 12], 6, 9),
+        ],
+        log.turns[..4]
+    );
+    // the die starts over within the 34th turn
+    assert_eq!(vec![100, 1, 2], log.turns[33].rolls);
+    assert_eq!(331, log.turns.len());
+    assert_eq!(993, log.rolls);
+    assert_eq!(vec![1000, 745], log.scores);
+    assert_eq!(0, log.winner);
+    assert_eq!(739785, log.outcome());
+
+    let mut die = SeededDie::new(6, 2021);
+    let rolls: Vec<u32> = (0..100).map(|_| die.roll()).collect();
+    assert!(rolls.iter().all(|eyes| (1..=6).contains(eyes)));
+    let mut again = SeededDie::new(6, 2021);
+    assert_eq!(rolls, (0..100).map(|_| again.roll()).collect::<Vec<_>>());
+    let log = PRACTICE.simulate(&players, &mut die).unwrap();
+    assert_eq!(log.rolls, 3 * log.turns.len() as u32);
+    assert!(log.scores[log.winner] >= 1000);
+
+    // a die that always shows 3 moves every player by 9, one space back
+    let mut die = LoadedDie::new(&[0, 0, 1], 7).unwrap();
+    let log = DIRAC.simulate(&players, &mut die).unwrap();
+    assert!(log.turns.iter().all(|turn| turn.rolls == [3, 3, 3]));
+    assert_eq!(
+        vec![3, 7, 2, 6],
+        log.turns[..4].iter().map(|t| t.pos).collect::<Vec<_>>()
+    );
+    assert!(LoadedDie::new(&[0, 0], 7).is_err());
+}